@@ -7,19 +7,26 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
 use self::proto_path_map::ProtoPathMap;
+use self::redact::{redact_debug_in_file, redacted_fields};
+use ::prost::Message;
 use prettyplease::unparse;
 use proc_macro2::TokenStream;
 pub use prost_build as prost;
-use prost_build::{Comments, Config, Module, Service, ServiceGenerator};
+use prost_build::{Comments, Config, Method, Module, Service, ServiceGenerator};
+use prost_types::FileDescriptorSet;
 use quote::{format_ident, quote};
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::fmt::Write;
 use std::io::{Error, ErrorKind, Result};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::{env, fs};
 use syn::{Item, parse_quote};
 
 mod proto_path_map;
+mod redact;
+mod typescript;
 
 /// Builds protobuf bindings for Twirp.
 ///
@@ -30,6 +37,9 @@ pub struct TwirpBuilder {
     generator: TwirpServiceGenerator,
     type_name_domain: Option<String>,
     skip_prost_reflect: bool,
+    typescript_client: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    verify_out_dir: bool,
 }
 
 impl TwirpBuilder {
@@ -45,6 +55,9 @@ impl TwirpBuilder {
             generator: TwirpServiceGenerator::new(),
             type_name_domain: None,
             skip_prost_reflect: false,
+            typescript_client: None,
+            out_dir: None,
+            verify_out_dir: false,
         }
     }
 
@@ -66,6 +79,57 @@ impl TwirpBuilder {
         self
     }
 
+    /// Generates a dyn-compatible variant of the server traits.
+    ///
+    /// For a service `Foo` it generates a `DynFoo` trait whose methods return boxed futures so it can be used as `Arc<dyn DynFoo>`.
+    /// It is implemented for all implementations of `Foo` and `Foo` is implemented for `Arc<dyn DynFoo>`,
+    /// so `into_router` can be called on it to pick the implementation at runtime.
+    pub fn with_dyn_server(mut self) -> Self {
+        self.generator = self.generator.with_dyn_server();
+        self
+    }
+
+    /// Generates default implementations of the server trait methods returning an `unimplemented` Twirp error.
+    ///
+    /// Adding a method to a service then does not break the existing implementations of the server trait,
+    /// like the `Unimplemented*Server` structs of Go Twirp.
+    pub fn with_default_unimplemented_methods(mut self) -> Self {
+        self.generator = self.generator.with_default_unimplemented_methods();
+        self
+    }
+
+    /// Generates a gRPC client alongside the Twirp one.
+    ///
+    /// For a service `Foo` it generates a `FooGrpcClient` struct built on [`tonic`](https://docs.rs/tonic/) that supports streaming methods.
+    /// The `grpc` feature of `twurst-client` must be enabled.
+    pub fn with_grpc_client(mut self) -> Self {
+        self.generator = self.generator.with_grpc_client();
+        self
+    }
+
+    /// Generates a blocking client alongside the async one.
+    ///
+    /// For a service `Foo` it generates a `FooBlockingClient` struct whose methods block until the response is received,
+    /// running the calls on an internal runtime. It is meant for synchronous code like CLI tools, build scripts or FFI.
+    /// Streaming methods are left out, like in the async Twirp client, use [`with_grpc_client`](Self::with_grpc_client) for them.
+    /// The `blocking` feature of `twurst-client` must be enabled.
+    pub fn with_blocking_client(mut self) -> Self {
+        self.generator = self.generator.with_blocking_client();
+        self
+    }
+
+    /// Generates a TypeScript Twirp client to the given file.
+    ///
+    /// The file contains interfaces for all messages following the [protobuf JSON mapping](https://protobuf.dev/programming-guides/json/),
+    /// a `FooClient` class for each service `Foo`, a `fetch` based `TwirpFetchTransport` and a typed `TwirpError`.
+    /// Streaming methods are not supported by Twirp and are skipped.
+    ///
+    /// Relative paths are relative to the crate root.
+    pub fn with_typescript_client(mut self, path: impl Into<PathBuf>) -> Self {
+        self.typescript_client = Some(path.into());
+        self
+    }
+
     #[deprecated(
         since = "0.3.1",
         note = "replaced with with_default_axum_request_extractor"
@@ -213,16 +277,82 @@ impl TwirpBuilder {
         self
     }
 
+    /// Adds an extra parameter to some server methods that implements [`axum::FromRequestParts`](https://docs.rs/axum/latest/axum/extract/trait.FromRequestParts.html).
+    ///
+    /// It works like [`TwirpBuilder::with_service_specific_axum_request_extractor`] but the method should be specified by Proto path
+    /// like `.MyPackage.MyService.MyMethod` or `MyService.MyMethod`.
+    /// Fully-qualified paths of a service or a package like `.MyPackage.MyService` and `.` are also accepted and apply to all their methods.
+    /// The extractors matching the service with [`TwirpBuilder::with_service_specific_axum_request_extractor`] come first.
+    ///
+    /// For example, to only require admin claims in the `Delete` method:
+    /// ```ignore
+    /// let builder = TwirpBuilder::new()
+    ///     .with_server()
+    ///     .with_default_axum_request_extractor("auth_header", "my_crate::AuthorizationHeader")
+    ///     .with_method_specific_axum_request_extractor(
+    ///         "auth_header",
+    ///         "my_crate::AuthorizationHeader",
+    ///         ".MyPackage.MyService.Delete",
+    ///     )
+    ///     .with_method_specific_axum_request_extractor(
+    ///         "admin_claims",
+    ///         "my_crate::AdminClaims",
+    ///         ".MyPackage.MyService.Delete",
+    ///     );
+    /// ```
+    ///
+    /// Like service specific extractors, method specific extractors overwrite the default ones and are not additive with them.
+    pub fn with_method_specific_axum_request_extractor(
+        mut self,
+        name: impl Into<String>,
+        type_name: impl Into<String>,
+        method_path: impl Into<String>,
+    ) -> Self {
+        self.generator = self.generator.with_method_specific_axum_request_extractor(
+            name,
+            type_name,
+            method_path,
+        );
+        self
+    }
+
     /// Skips the built-in prost-reflect configuration and file patching.
     ///
     /// When enabled, callers are responsible for configuring prost-reflect
     /// on the [`Config`] before passing it to [`from_prost`](Self::from_prost).
     /// This is useful when using a custom `out_dir`.
+    ///
+    /// With `protoc`, the `debug_redact` field options are then not available:
+    /// the `Debug` implementations of the messages are not redacted.
+    /// No warning is emitted as the option is dropped before it can be detected.
     pub fn skip_prost_reflect(mut self) -> Self {
         self.skip_prost_reflect = true;
         self
     }
 
+    /// Generates the files into the given directory instead of `OUT_DIR`.
+    ///
+    /// This allows to commit the generated code, including the `file_descriptor_set.bin` file used for reflection,
+    /// and to not need a build script.
+    /// The generated files can then be included with `include!` or declared as modules.
+    ///
+    /// Relative paths are relative to the current directory, i.e. the crate root in build scripts and tests.
+    pub fn with_out_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(path.into());
+        self
+    }
+
+    /// Instead of writing the generated files, checks that the existing ones are up to date.
+    ///
+    /// The files are generated in a temporary directory and compared with the ones in the out dir
+    /// (see [`with_out_dir`](Self::with_out_dir)) and with the TypeScript client if enabled.
+    /// Compilation fails with an error listing the missing or outdated files.
+    /// Other files in the out dir are ignored.
+    pub fn verify_out_dir(mut self, verify: bool) -> Self {
+        self.verify_out_dir = verify;
+        self
+    }
+
     /// Customizes the type name domain.
     ///
     /// By default, 'type.googleapis.com' is used.
@@ -237,15 +367,88 @@ impl TwirpBuilder {
         protos: &[impl AsRef<Path>],
         includes: &[impl AsRef<Path>],
     ) -> Result<()> {
-        let out_dir = PathBuf::from(
-            env::var_os("OUT_DIR").ok_or_else(|| Error::other("OUT_DIR is not set"))?,
-        );
-
         // We make sure the script is executed again if a file changed
         for proto in protos {
             println!("cargo:rerun-if-changed={}", proto.as_ref().display());
         }
 
+        let file_descriptor_set = self.load_fds(protos, includes)?;
+        self.compile_encoded_fds(file_descriptor_set)
+    }
+
+    #[cfg(feature = "protox")]
+    fn load_fds(
+        &mut self,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+    ) -> Result<Vec<u8>> {
+        // We parse the protos in-process instead of calling protoc
+        Ok(protox::Compiler::new(includes)
+            .map_err(Error::other)?
+            .include_source_info(true)
+            .include_imports(true)
+            .open_files(protos)
+            .map_err(Error::other)?
+            .encode_file_descriptor_set())
+    }
+
+    #[cfg(not(feature = "protox"))]
+    fn load_fds(
+        &mut self,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+    ) -> Result<Vec<u8>> {
+        if self.skip_prost_reflect {
+            // protoc has already been run when prost-reflect has been configured
+            self.config.skip_protoc_run();
+            return Ok(self.config.load_fds(protos, includes)?.encode_to_vec());
+        }
+        // We read the protoc output ourselves because prost-types drops some options like debug_redact
+        let temp_dir = tempfile::Builder::new().prefix("twurst-build").tempdir()?;
+        let file_descriptor_set_path = temp_dir.path().join("file_descriptor_set.bin");
+        self.config
+            .file_descriptor_set_path(&file_descriptor_set_path)
+            .load_fds(protos, includes)?;
+        fs::read(file_descriptor_set_path)
+    }
+
+    /// Do compile an already built [`FileDescriptorSet`].
+    ///
+    /// Useful when the descriptors come from `buf build`, a schema registry or any tool other than `protoc`.
+    /// The descriptors must contain all the imported files and, to get doc comments, the source code info.
+    ///
+    /// Note that [`FileDescriptorSet`] does not keep the `debug_redact` field options,
+    /// use [`compile_fds_from_path`](Self::compile_fds_from_path) to get redacted `Debug` implementations.
+    /// No warning is emitted as the option is dropped before it can be detected.
+    pub fn compile_fds(self, file_descriptor_set: FileDescriptorSet) -> Result<()> {
+        self.compile_encoded_fds(file_descriptor_set.encode_to_vec())
+    }
+
+    fn compile_encoded_fds(mut self, encoded_file_descriptor_set: Vec<u8>) -> Result<()> {
+        let file_descriptor_set = FileDescriptorSet::decode(encoded_file_descriptor_set.as_slice())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let redacted_fields = redacted_fields(&encoded_file_descriptor_set)?;
+        let out_dir = if let Some(out_dir) = &self.out_dir {
+            out_dir.clone()
+        } else {
+            PathBuf::from(env::var_os("OUT_DIR").ok_or_else(|| Error::other("OUT_DIR is not set"))?)
+        };
+
+        // When verifying, we generate in a temporary directory to compare it with the out dir
+        let temp_dir;
+        let generation_dir = if self.verify_out_dir {
+            temp_dir = tempfile::Builder::new().prefix("twurst-build").tempdir()?;
+            self.config.out_dir(temp_dir.path());
+            temp_dir.path().to_owned()
+        } else {
+            if self.out_dir.is_some() {
+                fs::create_dir_all(&out_dir)?;
+                self.config.out_dir(&out_dir);
+            }
+            out_dir.clone()
+        };
+
+        let generator_errors = self.generator.errors.clone();
         self.config
             .enable_type_names()
             .type_name_domain(
@@ -256,16 +459,31 @@ impl TwirpBuilder {
             )
             .service_generator(Box::new(self.generator));
 
-        // We configure with prost reflect
+        // We configure with prost reflect, it reads the descriptors from the file we write
         if !self.skip_prost_reflect {
+            let file_descriptor_set_path = generation_dir.join("file_descriptor_set.bin");
+            fs::write(&file_descriptor_set_path, &encoded_file_descriptor_set)?;
+            self.config.skip_protoc_run();
             prost_reflect_build::Builder::new()
+                .file_descriptor_set_path(file_descriptor_set_path)
                 .descriptor_pool("self::DESCRIPTOR_POOL")
-                .configure(&mut self.config, protos, includes)?;
+                .configure(&mut self.config, &[] as &[&Path], &[] as &[&Path])?;
         }
 
         // We do the build itself while saving the list of modules
-        let config = self.config.skip_protoc_run();
-        let file_descriptor_set = config.load_fds(protos, includes)?;
+        if let Some(typescript_client) = &self.typescript_client {
+            let typescript = typescript::generate_typescript(&file_descriptor_set);
+            if self.verify_out_dir {
+                if fs::read_to_string(typescript_client).ok().as_deref() != Some(&typescript) {
+                    return Err(Error::other(format!(
+                        "The TypeScript client {} is not up to date, it must be generated again",
+                        typescript_client.display()
+                    )));
+                }
+            } else {
+                fs::write(typescript_client, typescript)?;
+            }
+        }
         let modules = file_descriptor_set
             .file
             .iter()
@@ -273,23 +491,74 @@ impl TwirpBuilder {
             .collect::<HashSet<_>>();
 
         // We generate the files
-        config.compile_fds(file_descriptor_set)?;
+        self.config.compile_fds(file_descriptor_set)?;
+        let generator_errors = generator_errors.take();
+        if !generator_errors.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                generator_errors.join("\n"),
+            ));
+        }
 
         // We add the file descriptor to every file to make reflection work automatically
-        if !self.skip_prost_reflect {
+        // and replace the Debug implementations of the messages with redacted fields
+        if !self.skip_prost_reflect || !redacted_fields.is_empty() {
             for module in modules {
-                let file_path = Path::new(&out_dir).join(module.to_file_name_or("_"));
+                let file_path = generation_dir.join(module.to_file_name_or("_"));
                 if !file_path.exists() {
                     continue; // We ignore not built files
                 }
-                let original_content = fs::read_to_string(&file_path)?;
-                let modified_content = add_use_file_descriptor_to_file(&original_content)?;
-                fs::write(&file_path, &modified_content)?;
+                let mut content = fs::read_to_string(&file_path)?;
+                if !redacted_fields.is_empty() {
+                    content = redact_debug_in_file(&content, &redacted_fields)?;
+                }
+                if !self.skip_prost_reflect {
+                    content = add_use_file_descriptor_to_file(&content)?;
+                }
+                fs::write(&file_path, &content)?;
             }
         }
 
+        if self.verify_out_dir {
+            verify_generated_files(&generation_dir, &out_dir)?;
+        }
+
         Ok(())
     }
+
+    /// Do compile an encoded [`FileDescriptorSet`] file, e.g. the output of `buf build -o descriptors.binpb`.
+    ///
+    /// See [`compile_fds`](Self::compile_fds).
+    pub fn compile_fds_from_path(self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        println!("cargo:rerun-if-changed={}", path.display());
+        self.compile_encoded_fds(fs::read(path)?)
+    }
+}
+
+/// Checks that all files in `generated_dir` exist in `out_dir` with the same content
+fn verify_generated_files(generated_dir: &Path, out_dir: &Path) -> Result<()> {
+    let mut outdated_files = Vec::new();
+    for entry in fs::read_dir(generated_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let expected = fs::read(entry.path())?;
+        let actual = fs::read(out_dir.join(entry.file_name())).ok();
+        if actual.as_ref() != Some(&expected) {
+            outdated_files.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    if outdated_files.is_empty() {
+        return Ok(());
+    }
+    outdated_files.sort();
+    Err(Error::other(format!(
+        "The generated files in {} are not up to date, they must be generated again: {}",
+        out_dir.display(),
+        outdated_files.join(", ")
+    )))
 }
 
 fn add_use_file_descriptor_to_file(file: &str) -> Result<String> {
@@ -330,10 +599,18 @@ struct TwirpServiceGenerator {
     client: bool,
     server: bool,
     grpc: bool,
+    grpc_client: bool,
+    blocking_client: bool,
+    dyn_server: bool,
+    default_unimplemented: bool,
     // stores the default extractors as (argument_name, extractor_type)
     default_request_extractors: Vec<(String, String)>,
-    // stores an extractor for a proto path as (argument_name, extractor_type)
-    matched_request_extractors: ProtoPathMap<(String, String)>,
+    // stores an extractor for a service proto path as (argument_name, extractor_type)
+    service_request_extractors: ProtoPathMap<(String, String)>,
+    // stores an extractor for a method proto path as (argument_name, extractor_type)
+    method_request_extractors: ProtoPathMap<(String, String)>,
+    // errors found during the generation, prost does not allow service generators to fail
+    errors: Rc<RefCell<Vec<String>>>,
 }
 
 impl TwirpServiceGenerator {
@@ -356,6 +633,26 @@ impl TwirpServiceGenerator {
         self
     }
 
+    pub fn with_grpc_client(mut self) -> Self {
+        self.grpc_client = true;
+        self
+    }
+
+    pub fn with_blocking_client(mut self) -> Self {
+        self.blocking_client = true;
+        self
+    }
+
+    pub fn with_dyn_server(mut self) -> Self {
+        self.dyn_server = true;
+        self
+    }
+
+    pub fn with_default_unimplemented_methods(mut self) -> Self {
+        self.default_unimplemented = true;
+        self
+    }
+
     pub fn with_default_axum_request_extractor(
         mut self,
         name: impl Into<String>,
@@ -366,41 +663,170 @@ impl TwirpServiceGenerator {
         self
     }
 
-    // This will override any and all default extractors, but only for the services which match service_proto_path.
-    pub fn with_service_specific_axum_request_extractor(
+    // This will override any and all default extractors, but only for the methods which match method_proto_path.
+    pub fn with_method_specific_axum_request_extractor(
         mut self,
         name: impl Into<String>,
         type_name: impl Into<String>,
-        service_proto_path: impl Into<String>,
+        method_proto_path: impl Into<String>,
     ) -> Self {
-        self.matched_request_extractors
-            .insert(service_proto_path.into(), (name.into(), type_name.into()));
+        self.method_request_extractors
+            .insert(method_proto_path.into(), (name.into(), type_name.into()));
         self
     }
-}
 
-impl ServiceGenerator for TwirpServiceGenerator {
-    fn generate(&mut self, service: Service, buf: &mut String) {
-        let mut service_matches = self
-            .matched_request_extractors
-            .service_matches(&service)
-            .peekable();
-        let extractors = if service_matches.peek().is_some() {
-            service_matches.collect::<Vec<_>>()
+    /// Returns the names and types of the extractors of a method.
+    ///
+    /// Extractors matching the service or the method override the default ones.
+    /// Service matchers are only checked against the service path and method matchers against the method path.
+    fn request_extractors(
+        &self,
+        service: &Service,
+        method: &Method,
+    ) -> (Vec<proc_macro2::Ident>, Vec<TokenStream>) {
+        let matches = self
+            .service_request_extractors
+            .service_matches(service)
+            .chain(
+                self.method_request_extractors
+                    .method_matches(service, method),
+            )
+            .collect::<Vec<_>>();
+        let extractors = if !matches.is_empty() {
+            matches
         } else {
             self.default_request_extractors.iter().collect()
         };
+        extractors
+            .into_iter()
+            .map(|(n, t)| (format_ident!("{n}"), t.parse().unwrap()))
+            .unzip()
+    }
 
-        let extractor_names = extractors
+    // This will override any and all default extractors, but only for the services which match service_proto_path.
+    pub fn with_service_specific_axum_request_extractor(
+        mut self,
+        name: impl Into<String>,
+        type_name: impl Into<String>,
+        service_proto_path: impl Into<String>,
+    ) -> Self {
+        self.service_request_extractors
+            .insert(service_proto_path.into(), (name.into(), type_name.into()));
+        self
+    }
+
+    /// Reports the generated client methods whose name is already the one of a method of the service,
+    /// e.g. `foo_with_metadata` for the methods `Foo` and `FooWithMetadata`
+    fn check_client_method_names(&self, service: &Service) {
+        let mut suffixes = vec!["_with_metadata"];
+        if self.client {
+            suffixes.push("_builder");
+        }
+        let method_names = service
+            .methods
             .iter()
-            .map(|(n, _)| format_ident!("{n}"))
-            .collect::<Vec<_>>();
-        let extractor_types = extractors
+            .map(|m| m.name.as_str())
+            .collect::<HashSet<_>>();
+        for method in &service.methods {
+            if method.client_streaming || method.server_streaming {
+                continue;
+            }
+            for suffix in &suffixes {
+                let generated_name = format!("{}{suffix}", method.name);
+                if method_names.contains(generated_name.as_str()) {
+                    self.errors.borrow_mut().push(format!(
+                        "The client method {generated_name} generated for the method {} of the service {}.{} conflicts with the method {generated_name} of the same service",
+                        method.proto_name, service.package, service.proto_name
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Generates the dyn-compatible `Dyn{Service}` trait and its adapters from and to the `{Service}` trait
+    fn generate_dyn_server(&self, service: &Service) -> TokenStream {
+        let service_name_ident = format_ident!("{}", service.name);
+        let dyn_service_name_ident = format_ident!("Dyn{}", service.name);
+        let dyn_service_doc = format!(
+            "Dyn-compatible variant of [`{}`] usable as `Arc<dyn {dyn_service_name_ident}>`.",
+            service.name
+        );
+
+        let mut dyn_method_tokens = Vec::new();
+        let mut to_dyn_method_tokens = Vec::new();
+        let mut from_dyn_method_tokens = Vec::new();
+        for method in service
+            .methods
             .iter()
-            .map(|(_, t)| t.parse().unwrap())
-            .collect::<Vec<TokenStream>>();
+            .filter(|m| self.grpc || (!m.client_streaming && !m.server_streaming))
+        {
+            let method_ident = format_ident!("{}", method.name);
+            let input_type: TokenStream = method.input_type.parse().unwrap();
+            let output_type: TokenStream = method.output_type.parse().unwrap();
+            let method_docs = quote_comments(&method.comments);
+            let (extractor_names, extractor_types) = self.request_extractors(service, method);
+            let (request_param, dyn_request_param, boxed_request) = if method.client_streaming {
+                (
+                    quote! {
+                        impl ::twurst_server::codegen::Stream<Item=Result<#input_type,::twurst_client::TwirpError>> + Send + 'static
+                    },
+                    quote! {
+                        ::std::pin::Pin<Box<dyn ::twurst_server::codegen::Stream<Item=Result<#input_type,::twurst_client::TwirpError>> + Send>>
+                    },
+                    quote! { Box::pin(request) },
+                )
+            } else {
+                (input_type.clone(), input_type, quote! { request })
+            };
+            let return_type = if method.server_streaming {
+                quote! {
+                    Box<dyn ::twurst_server::codegen::Stream<Item=Result<#output_type, ::twurst_server::TwirpError>> + Send>
+                }
+            } else {
+                output_type
+            };
+
+            dyn_method_tokens.push(quote! {
+                #(#method_docs)*
+                fn #method_ident<'a>(&'a self, request: #dyn_request_param #(, #extractor_names: #extractor_types)*) -> ::twurst_server::codegen::BoxFuture<'a, Result<#return_type, ::twurst_server::TwirpError>>;
+            });
+            to_dyn_method_tokens.push(quote! {
+                fn #method_ident<'a>(&'a self, request: #dyn_request_param #(, #extractor_names: #extractor_types)*) -> ::twurst_server::codegen::BoxFuture<'a, Result<#return_type, ::twurst_server::TwirpError>> {
+                    Box::pin(#service_name_ident::#method_ident(self, request #(, #extractor_names)*))
+                }
+            });
+            from_dyn_method_tokens.push(quote! {
+                async fn #method_ident(&self, request: #request_param #(, #extractor_names: #extractor_types)*) -> Result<#return_type, ::twurst_server::TwirpError> {
+                    #dyn_service_name_ident::#method_ident(&**self, #boxed_request #(, #extractor_names)*).await
+                }
+            });
+        }
+
+        quote! {
+            #[doc = #dyn_service_doc]
+            #[allow(clippy::type_complexity)]
+            pub trait #dyn_service_name_ident: Send + Sync {
+                #(#dyn_method_tokens)*
+            }
+
+            #[allow(clippy::type_complexity)]
+            impl<T: #service_name_ident + Send + Sync> #dyn_service_name_ident for T {
+                #(#to_dyn_method_tokens)*
+            }
+
+            impl #service_name_ident for ::std::sync::Arc<dyn #dyn_service_name_ident> {
+                #(#from_dyn_method_tokens)*
+            }
+        }
+    }
+}
 
+impl ServiceGenerator for TwirpServiceGenerator {
+    fn generate(&mut self, service: Service, buf: &mut String) {
         let mut output = TokenStream::new();
+        if self.client || self.blocking_client {
+            self.check_client_method_names(&service);
+        }
         if self.client {
             let client_name = format_ident!("{}Client", service.name);
             let service_docs = quote_comments(&service.comments);
@@ -428,12 +854,36 @@ impl ServiceGenerator for TwirpServiceGenerator {
                     } else {
                         quote! {}
                     };
+                    let method_with_metadata_ident = format_ident!("{}_with_metadata", method.name);
+                    let method_with_metadata_doc = format!(
+                        " Like [`Self::{}`] but also returns the response metadata (status, headers and extensions).",
+                        method.name
+                    );
+                    let method_builder_ident = format_ident!("{}_builder", method.name);
+                    let method_builder_doc = format!(
+                        " Like [`Self::{}`] but returns a builder to customize the call (headers, timeout, encoding) before sending it with `.send().await`.",
+                        method.name
+                    );
                     quote! {
                         #(#method_docs)*
                         #method_deprecated
                         pub async fn #method_ident(&self, request: &#input_type) -> Result<#output_type, ::twurst_client::TwirpError> {
                             self.client.call(#route, request).await
                         }
+
+                        #[doc = #method_builder_doc]
+                        #method_deprecated
+                        #[allow(dead_code)]
+                        pub fn #method_builder_ident<'a>(&'a self, request: &'a #input_type) -> ::twurst_client::TypedTwirpCallBuilder<'a, C, #input_type, #output_type> {
+                            self.client.call_builder(#route, request).typed()
+                        }
+
+                        #[doc = #method_with_metadata_doc]
+                        #method_deprecated
+                        #[allow(dead_code)]
+                        pub async fn #method_with_metadata_ident(&self, request: &#input_type) -> Result<::twurst_client::TwirpResponse<#output_type>, ::twurst_client::TwirpError> {
+                            self.client.call_builder(#route, request).send_with_metadata().await
+                        }
                     }
                 })
                 .collect::<Vec<_>>();
@@ -455,6 +905,148 @@ impl ServiceGenerator for TwirpServiceGenerator {
             });
         }
 
+        if self.blocking_client {
+            let client_name = format_ident!("{}BlockingClient", service.name);
+            let service_docs = quote_comments(&service.comments);
+            let service_deprecated = if service.options.deprecated.unwrap_or(false) {
+                Some(quote! { #[deprecated] })
+            } else {
+                None
+            };
+
+            let method_tokens = service
+                .methods
+                .iter()
+                .filter(|m| !m.client_streaming && !m.server_streaming)
+                .map(|method| {
+                    let method_ident = format_ident!("{}", method.name);
+                    let input_type: TokenStream = method.input_type.parse().unwrap();
+                    let output_type: TokenStream = method.output_type.parse().unwrap();
+                    let route = format!(
+                        "/{}.{}/{}",
+                        service.package, service.proto_name, method.proto_name
+                    );
+                    let method_docs = quote_comments(&method.comments);
+                    let method_deprecated = if method.options.deprecated.unwrap_or(false) {
+                        quote! { #[deprecated] }
+                    } else {
+                        quote! {}
+                    };
+                    let method_with_metadata_ident = format_ident!("{}_with_metadata", method.name);
+                    let method_with_metadata_doc = format!(
+                        " Like [`Self::{}`] but also returns the response metadata (status, headers and extensions).",
+                        method.name
+                    );
+                    quote! {
+                        #(#method_docs)*
+                        #method_deprecated
+                        pub fn #method_ident(&self, request: &#input_type) -> Result<#output_type, ::twurst_client::TwirpError> {
+                            self.client.call(#route, request)
+                        }
+
+                        #[doc = #method_with_metadata_doc]
+                        #method_deprecated
+                        #[allow(dead_code)]
+                        pub fn #method_with_metadata_ident(&self, request: &#input_type) -> Result<::twurst_client::TwirpResponse<#output_type>, ::twurst_client::TwirpError> {
+                            self.client.call_with_metadata(#route, request)
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            output.extend(quote! {
+                #(#service_docs)*
+                #service_deprecated
+                #[derive(Clone)]
+                pub struct #client_name<C: ::twurst_client::TwirpHttpService> {
+                    client: ::twurst_client::blocking::BlockingTwirpHttpClient<C>,
+                }
+
+                impl<C: ::twurst_client::TwirpHttpService> #client_name<C> {
+                    /// Builds the client and its internal runtime
+                    pub fn new(client: impl Into<::twurst_client::TwirpHttpClient<C>>) -> ::std::io::Result<Self> {
+                        Ok(Self { client: ::twurst_client::blocking::BlockingTwirpHttpClient::new(client)? })
+                    }
+                    #(#method_tokens)*
+                }
+
+                impl<C: ::twurst_client::TwirpHttpService> From<::twurst_client::blocking::BlockingTwirpHttpClient<C>> for #client_name<C> {
+                    fn from(client: ::twurst_client::blocking::BlockingTwirpHttpClient<C>) -> Self {
+                        Self { client }
+                    }
+                }
+            });
+        }
+
+        if self.grpc_client {
+            let client_name = format_ident!("{}GrpcClient", service.name);
+            let service_docs = quote_comments(&service.comments);
+            let service_deprecated = if service.options.deprecated.unwrap_or(false) {
+                Some(quote! { #[deprecated] })
+            } else {
+                None
+            };
+
+            let method_tokens = service
+                .methods
+                .iter()
+                .map(|method| {
+                    let method_ident = format_ident!("{}", method.name);
+                    let input_type: TokenStream = method.input_type.parse().unwrap();
+                    let output_type: TokenStream = method.output_type.parse().unwrap();
+                    let route = format!(
+                        "/{}.{}/{}",
+                        service.package, service.proto_name, method.proto_name
+                    );
+                    let method_docs = quote_comments(&method.comments);
+                    let method_deprecated = if method.options.deprecated.unwrap_or(false) {
+                        quote! { #[deprecated] }
+                    } else {
+                        quote! {}
+                    };
+                    let request_param = if method.client_streaming {
+                        quote! { impl ::twurst_client::codegen::Stream<Item = #input_type> + Send + 'static }
+                    } else {
+                        quote! { &#input_type }
+                    };
+                    let return_type = if method.server_streaming {
+                        quote! { ::twurst_client::GrpcStream<#output_type> }
+                    } else {
+                        output_type
+                    };
+                    let client_fn_ident = match (method.client_streaming, method.server_streaming) {
+                        (false, false) => format_ident!("unary"),
+                        (false, true) => format_ident!("server_streaming"),
+                        (true, false) => format_ident!("client_streaming"),
+                        (true, true) => format_ident!("streaming"),
+                    };
+                    quote! {
+                        #(#method_docs)*
+                        #method_deprecated
+                        pub async fn #method_ident(&self, request: #request_param) -> Result<#return_type, ::twurst_client::TwirpError> {
+                            self.client.#client_fn_ident(#route, request).await
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            output.extend(quote! {
+                #(#service_docs)*
+                #service_deprecated
+                #[derive(Clone)]
+                pub struct #client_name<C: ::twurst_client::GrpcHttpService> {
+                    client: ::twurst_client::GrpcClient<C>,
+                }
+
+                impl<C: ::twurst_client::GrpcHttpService> #client_name<C> {
+                    pub fn new(client: impl Into<::twurst_client::GrpcClient<C>>) -> Self {
+                        Self { client: client.into() }
+                    }
+                    #(#method_tokens)*
+                }
+            });
+        }
+
         if self.server {
             let service_name_ident = format_ident!("{}", service.name);
 
@@ -469,6 +1061,8 @@ impl ServiceGenerator for TwirpServiceGenerator {
                     let input_type: TokenStream = method.input_type.parse().unwrap();
                     let output_type: TokenStream = method.output_type.parse().unwrap();
                     let method_docs = quote_comments(&method.comments);
+                    let (extractor_names, extractor_types) =
+                        self.request_extractors(&service, method);
                     let request_param = if method.client_streaming {
                         quote! {
                             impl ::twurst_server::codegen::Stream<Item=Result<#input_type,::twurst_client::TwirpError>> + Send + 'static
@@ -486,9 +1080,25 @@ impl ServiceGenerator for TwirpServiceGenerator {
                         output_type
                     };
 
-                    quote! {
-                        #(#method_docs)*
-                        async fn #method_ident(&self, request: #request_param #(, #extractor_names: #extractor_types)*) -> Result<#return_type, ::twurst_server::TwirpError>;
+                    if self.default_unimplemented {
+                        let route = format!(
+                            "/{}.{}/{}",
+                            service.package, service.proto_name, method.proto_name
+                        );
+                        let message = format!("{route} is not implemented");
+                        // Not an async fn because trait_variant does not wrap default bodies in an async block
+                        quote! {
+                            #(#method_docs)*
+                            #[allow(unused_variables)]
+                            fn #method_ident(&self, request: #request_param #(, #extractor_names: #extractor_types)*) -> impl ::std::future::Future<Output = Result<#return_type, ::twurst_server::TwirpError>> {
+                                ::std::future::ready(Err(::twurst_server::TwirpError::unimplemented(#message)))
+                            }
+                        }
+                    } else {
+                        quote! {
+                            #(#method_docs)*
+                            async fn #method_ident(&self, request: #request_param #(, #extractor_names: #extractor_types)*) -> Result<#return_type, ::twurst_server::TwirpError>;
+                        }
                     }
                 })
                 .collect::<Vec<_>>();
@@ -507,7 +1117,8 @@ impl ServiceGenerator for TwirpServiceGenerator {
                     if method.client_streaming || method.server_streaming {
                         quote! { .route_streaming(#route) }
                     } else {
-                        let (parts_param, state_param) = if extractors.is_empty() {
+                        let (_, extractor_types) = self.request_extractors(&service, method);
+                        let (parts_param, state_param) = if extractor_types.is_empty() {
                             (
                                 quote! { _: ::twurst_server::codegen::RequestParts },
                                 quote! { _: S },
@@ -519,12 +1130,10 @@ impl ServiceGenerator for TwirpServiceGenerator {
                             )
                         };
 
-                        let ext_types = extractor_types.clone();
-
                         quote! {
                             .route(#route, |service: ::std::sync::Arc<Self>, request: #input_type, #parts_param, #state_param| {
                                 async move {
-                                    service.#method_ident(request #(, match <#ext_types as ::twurst_server::codegen::FromRequestParts<_>>::from_request_parts(&mut parts, &state).await { Ok(r) => r, Err(e) => { return Err(::twurst_server::codegen::twirp_error_from_response(e).await) } })*).await
+                                    service.#method_ident(request #(, match <#extractor_types as ::twurst_server::codegen::FromRequestParts<_>>::from_request_parts(&mut parts, &state).await { Ok(r) => r, Err(e) => { return Err(::twurst_server::codegen::twirp_error_from_response(e).await) } })*).await
                                 }
                             })
                         }
@@ -555,7 +1164,8 @@ impl ServiceGenerator for TwirpServiceGenerator {
                         } else {
                             input_type
                         };
-                        let parts_param = if extractors.is_empty() {
+                        let (_, extractor_types) = self.request_extractors(&service, method);
+                        let parts_param = if extractor_types.is_empty() {
                             quote! { _: ::twurst_server::codegen::RequestParts }
                         } else {
                             quote! { mut parts: ::twurst_server::codegen::RequestParts }
@@ -604,6 +1214,10 @@ impl ServiceGenerator for TwirpServiceGenerator {
                     #grpc_router_tokens
                 }
             });
+
+            if self.dyn_server {
+                output.extend(self.generate_dyn_server(&service));
+            }
         }
 
         if !output.is_empty() {
@@ -642,4 +1256,55 @@ mod tests {
             "expected a single DESCRIPTOR_POOL declaration, got:\n{output}"
         );
     }
+
+    #[test]
+    fn verifies_checked_in_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let proto = dir.path().join("test.proto");
+        fs::write(
+            &proto,
+            "syntax = \"proto3\";\npackage test;\nmessage Foo { string bar = 1; }\n",
+        )?;
+        let out_dir = dir.path().join("generated");
+        let build = |verify| {
+            TwirpBuilder::new()
+                .with_server()
+                .with_out_dir(&out_dir)
+                .verify_out_dir(verify)
+                .compile_protos(&[&proto], &[dir.path()])
+        };
+
+        build(false)?;
+        assert!(out_dir.join("test.rs").exists());
+        assert!(out_dir.join("file_descriptor_set.bin").exists());
+        build(true)?;
+
+        fs::write(out_dir.join("test.rs"), "// Modified")?;
+        let error = build(true).unwrap_err();
+        assert!(error.to_string().contains("test.rs"), "{error}");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_conflicting_client_method_names() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let proto = dir.path().join("test.proto");
+        fs::write(
+            &proto,
+            "syntax = \"proto3\";\npackage test;\nmessage Empty {}\nservice Test {\n  rpc Foo(Empty) returns (Empty);\n  rpc FooBuilder(Empty) returns (Empty);\n}\n",
+        )?;
+        let error = TwirpBuilder::new()
+            .with_client()
+            .with_out_dir(dir.path().join("generated"))
+            .compile_protos(&[&proto], &[dir.path()])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput, "{error}");
+        assert!(
+            error
+                .to_string()
+                .contains("foo_builder generated for the method Foo"),
+            "{error}"
+        );
+        Ok(())
+    }
 }
@@ -1,7 +1,7 @@
 //! Maps a fully-qualified Protobuf path to a value using path matchers.
 //! Original implementation: <https://github.com/tokio-rs/prost/blob/5a2c7092964ac2eaaa516c61bcd48e3c66ea16b3/prost-build/src/path.rs>
 
-use prost_build::Service;
+use prost_build::{Method, Service};
 use std::iter;
 
 #[derive(Clone, Debug, Default)]
@@ -15,12 +15,21 @@ impl<T> ProtoPathMap<T> {
         self.matchers.push((matcher, value));
     }
 
-    /// Returns a iterator over all the values matching the given path
+    /// Returns a iterator over all the values matching the given service path
     pub(crate) fn service_matches(&self, service: &Service) -> Iter<'_, T> {
         let fq_path = format!(".{}.{}", service.package, service.proto_name);
         self.fq_path_matches(&fq_path)
     }
 
+    /// Returns a iterator over all the values matching the given method path
+    pub(crate) fn method_matches(&self, service: &Service, method: &Method) -> Iter<'_, T> {
+        let fq_path = format!(
+            ".{}.{}.{}",
+            service.package, service.proto_name, method.proto_name
+        );
+        self.fq_path_matches(&fq_path)
+    }
+
     /// Returns a iterator over all the values matching the given fully-qualified proto path
     pub(crate) fn fq_path_matches(&self, fq_path: &str) -> Iter<'_, T> {
         Iter::new(self, fq_path.to_string())
@@ -247,4 +256,82 @@ mod tests {
         let mut iter = path_map.service_matches(&alternate_service);
         assert_eq!(None, iter.next());
     }
+
+    #[test]
+    fn test_method_matches() {
+        let mut path_map = ProtoPathMap::default();
+        path_map.insert("Service".to_owned(), 1);
+        path_map.insert(".a.Service.Admin".to_owned(), 2);
+        path_map.insert("Other.Admin".to_owned(), 3);
+
+        let service = Service {
+            proto_name: "Service".to_string(),
+            package: "a".to_string(),
+            name: "Service".to_string(),
+            comments: Comments::default(),
+            methods: Vec::new(),
+            options: prost_types::ServiceOptions {
+                deprecated: None,
+                uninterpreted_option: Vec::new(),
+            },
+        };
+        let method = |name: &str| Method {
+            name: name.to_lowercase(),
+            proto_name: name.to_string(),
+            comments: Comments::default(),
+            input_type: "Input".to_string(),
+            output_type: "Output".to_string(),
+            input_proto_type: ".a.Input".to_string(),
+            output_proto_type: ".a.Output".to_string(),
+            options: prost_types::MethodOptions::default(),
+            client_streaming: false,
+            server_streaming: false,
+        };
+
+        // The bare service name is not a suffix of the method paths
+        let mut iter = path_map.method_matches(&service, &method("Admin"));
+        assert_eq!(Some(&2), iter.next());
+        assert_eq!(None, iter.next());
+
+        let mut iter = path_map.method_matches(&service, &method("Public"));
+        assert_eq!(None, iter.next());
+
+        // The fully-qualified service path is a prefix of the method paths
+        path_map.clear();
+        path_map.insert(".a.Service".to_owned(), 1);
+        let mut iter = path_map.method_matches(&service, &method("Public"));
+        assert_eq!(Some(&1), iter.next());
+    }
+
+    #[test]
+    fn test_service_matcher_ignores_method_names() {
+        let mut path_map = ProtoPathMap::default();
+        path_map.insert("Test".to_owned(), 1);
+
+        let service = Service {
+            proto_name: "Service".to_string(),
+            package: "a".to_string(),
+            name: "Service".to_string(),
+            comments: Comments::default(),
+            methods: vec![Method {
+                name: "test".to_string(),
+                proto_name: "Test".to_string(),
+                comments: Comments::default(),
+                input_type: "Input".to_string(),
+                output_type: "Output".to_string(),
+                input_proto_type: ".a.Input".to_string(),
+                output_proto_type: ".a.Output".to_string(),
+                options: prost_types::MethodOptions::default(),
+                client_streaming: false,
+                server_streaming: false,
+            }],
+            options: prost_types::ServiceOptions {
+                deprecated: None,
+                uninterpreted_option: Vec::new(),
+            },
+        };
+
+        // "Test" is only compared to the service path, not to ".a.Service.Test"
+        assert_eq!(None, path_map.service_matches(&service).next());
+    }
 }
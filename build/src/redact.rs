@@ -0,0 +1,444 @@
+//! Generates `Debug` implementations hiding the fields annotated with `debug_redact = true`.
+//!
+//! prost derives `Debug` for all the messages, we disable it with `#[prost(skip_debug)]`
+//! on the messages and oneofs with redacted fields and implement it ourselves.
+//! Like prost, the enumeration fields are printed with the name of their value if it is known.
+
+use prettyplease::unparse;
+use proc_macro2::TokenStream;
+use prost_reflect::{DescriptorPool, FieldDescriptor, Value};
+use quote::quote;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Result};
+use syn::ext::IdentExt;
+use syn::{Attribute, Expr, Fields, ImplItem, Item, LitStr, Token, parse_quote};
+
+/// Tags of the redacted fields indexed by fully-qualified message name
+pub(crate) type RedactedFields = HashMap<String, HashSet<u32>>;
+
+/// Returns the redacted fields of the messages defined in the encoded `FileDescriptorSet`
+pub(crate) fn redacted_fields(encoded_file_descriptor_set: &[u8]) -> Result<RedactedFields> {
+    let pool = DescriptorPool::decode(encoded_file_descriptor_set)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let mut redacted = RedactedFields::new();
+    for message in pool.all_messages() {
+        for field in message.fields() {
+            if is_redacted(&field) {
+                redacted
+                    .entry(message.full_name().into())
+                    .or_default()
+                    .insert(field.number());
+            }
+        }
+    }
+    Ok(redacted)
+}
+
+fn is_redacted(field: &FieldDescriptor) -> bool {
+    field
+        .options()
+        .get_field_by_name("debug_redact")
+        .is_some_and(|value| matches!(*value, Value::Bool(true)))
+}
+
+pub(crate) fn redact_debug_in_file(file: &str, redacted: &RedactedFields) -> Result<String> {
+    let mut ast = syn::parse_file(file).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    redact_debug_in_items(&mut ast.items, redacted)?;
+    Ok(unparse(&ast))
+}
+
+fn redact_debug_in_items(items: &mut Vec<Item>, redacted: &RedactedFields) -> Result<()> {
+    // prost generates a `prost::Name` implementation next to each message, we use it to get the message name
+    let full_names = items
+        .iter()
+        .filter_map(message_full_name)
+        .collect::<HashMap<_, _>>();
+
+    let mut new_items = Vec::new();
+    // Oneofs with redacted fields indexed by (module, enum) name
+    let mut redacted_oneofs = HashMap::<(String, String), HashSet<u32>>::new();
+    for item in items.iter_mut() {
+        let Item::Struct(message) = item else {
+            continue;
+        };
+        let Some(redacted_tags) = full_names
+            .get(&message.ident.to_string())
+            .and_then(|full_name| redacted.get(full_name))
+        else {
+            continue;
+        };
+        if has_skip_debug(&message.attrs) {
+            continue; // The user provides their own implementation
+        }
+        let Fields::Named(fields) = &message.fields else {
+            continue;
+        };
+        let mut field_debugs = Vec::new();
+        let mut has_redacted_field = false;
+        let mut has_enumeration = false;
+        for field in &fields.named {
+            let Some(ident) = &field.ident else {
+                continue;
+            };
+            let attribute = ProstAttribute::parse(&field.attrs)?;
+            if let Some(oneof) = &attribute.oneof {
+                let oneof_tags = attribute
+                    .tags
+                    .iter()
+                    .copied()
+                    .filter(|tag| redacted_tags.contains(tag))
+                    .collect::<HashSet<_>>();
+                if let Some((module, name)) = oneof.rsplit_once("::")
+                    && !oneof_tags.is_empty()
+                {
+                    redacted_oneofs.insert((module.into(), name.into()), oneof_tags);
+                }
+            }
+            let name = ident.unraw().to_string();
+            if attribute
+                .tag
+                .is_some_and(|tag| redacted_tags.contains(&tag))
+            {
+                has_redacted_field = true;
+                field_debugs.push(quote! { .field(#name, &::core::format_args!("[REDACTED]")) });
+            } else if let Some(value) = attribute.enumeration_debug(quote! { self.#ident })? {
+                has_enumeration = true;
+                field_debugs.push(quote! { .field(#name, &#value) });
+            } else {
+                field_debugs.push(quote! { .field(#name, &self.#ident) });
+            }
+        }
+        if !has_redacted_field {
+            continue;
+        }
+        message.attrs.push(parse_quote! { #[prost(skip_debug)] });
+        let ident = &message.ident;
+        let name = ident.to_string();
+        let helpers = has_enumeration.then(enumeration_debug_helpers);
+        new_items.push(parse_quote! {
+            #[allow(deprecated)]
+            impl ::core::fmt::Debug for #ident {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    #helpers
+                    f.debug_struct(#name) #(#field_debugs)* .finish()
+                }
+            }
+        });
+    }
+
+    for item in items.iter_mut() {
+        let Item::Mod(module) = item else {
+            continue;
+        };
+        let Some((_, module_items)) = &mut module.content else {
+            continue;
+        };
+        let mut new_module_items = Vec::new();
+        for module_item in module_items.iter_mut() {
+            let Item::Enum(oneof) = module_item else {
+                continue;
+            };
+            let Some(redacted_tags) =
+                redacted_oneofs.get(&(module.ident.to_string(), oneof.ident.to_string()))
+            else {
+                continue;
+            };
+            if has_skip_debug(&oneof.attrs) {
+                continue;
+            }
+            let mut arms = Vec::new();
+            let mut has_enumeration = false;
+            for variant in &oneof.variants {
+                let ident = &variant.ident;
+                let name = ident.unraw().to_string();
+                let attribute = ProstAttribute::parse(&variant.attrs)?;
+                if attribute
+                    .tag
+                    .is_some_and(|tag| redacted_tags.contains(&tag))
+                {
+                    arms.push(quote! {
+                        Self::#ident(_) => f.debug_tuple(#name).field(&::core::format_args!("[REDACTED]")).finish()
+                    });
+                } else if let Some(value) = attribute.enumeration_debug(quote! { *value })? {
+                    has_enumeration = true;
+                    arms.push(quote! {
+                        Self::#ident(value) => f.debug_tuple(#name).field(&#value).finish()
+                    });
+                } else {
+                    arms.push(quote! {
+                        Self::#ident(value) => f.debug_tuple(#name).field(value).finish()
+                    });
+                }
+            }
+            oneof.attrs.push(parse_quote! { #[prost(skip_debug)] });
+            let ident = &oneof.ident;
+            let helpers = has_enumeration.then(enumeration_debug_helpers);
+            new_module_items.push(parse_quote! {
+                #[allow(deprecated)]
+                impl ::core::fmt::Debug for #ident {
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                        #helpers
+                        match self {
+                            #(#arms,)*
+                        }
+                    }
+                }
+            });
+        }
+        module_items.extend(new_module_items);
+        redact_debug_in_items(module_items, redacted)?;
+    }
+
+    items.extend(new_items);
+    Ok(())
+}
+
+/// Returns the Rust name and the Protobuf full name of the message if the item is a `prost::Name` implementation
+fn message_full_name(item: &Item) -> Option<(String, String)> {
+    let Item::Impl(implementation) = item else {
+        return None;
+    };
+    let (trait_path, _) = implementation.trait_.as_ref()?;
+    if trait_path.segments.last().is_none_or(|s| s.ident != "Name") {
+        return None;
+    }
+    let syn::Type::Path(self_type) = implementation.self_ty.as_ref() else {
+        return None;
+    };
+    let message_name = self_type.path.get_ident()?.to_string();
+    implementation.items.iter().find_map(|item| {
+        let ImplItem::Fn(function) = item else {
+            return None;
+        };
+        if function.sig.ident != "full_name" {
+            return None;
+        }
+        // The body is `"package.Message".into()`
+        let Some(syn::Stmt::Expr(Expr::MethodCall(call), None)) = function.block.stmts.first()
+        else {
+            return None;
+        };
+        let Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(full_name),
+            ..
+        }) = call.receiver.as_ref()
+        else {
+            return None;
+        };
+        Some((message_name.clone(), full_name.value()))
+    })
+}
+
+fn has_skip_debug(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("prost")
+            && attr
+                .parse_nested_meta(|meta| {
+                    if meta.path.is_ident("skip_debug") {
+                        Err(meta.error("found"))
+                    } else {
+                        skip_meta_value(&meta)
+                    }
+                })
+                .is_err()
+    })
+}
+
+/// Functions used by the `Debug` implementations to print the enumeration values like prost does
+fn enumeration_debug_helpers() -> TokenStream {
+    quote! {
+        struct DebugFn<F>(F);
+
+        impl<F: Fn(&mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result> ::core::fmt::Debug for DebugFn<F> {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                (self.0)(f)
+            }
+        }
+
+        fn debug_fn<F: Fn(&mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result>(f: F) -> DebugFn<F> {
+            DebugFn(f)
+        }
+
+        fn enumeration<E: ::core::convert::TryFrom<i32> + ::core::fmt::Debug>(
+            value: i32,
+            f: &mut ::core::fmt::Formatter<'_>,
+        ) -> ::core::fmt::Result {
+            match <E as ::core::convert::TryFrom<i32>>::try_from(value) {
+                Ok(value) => ::core::fmt::Debug::fmt(&value, f),
+                Err(_) => ::core::fmt::Debug::fmt(&value, f),
+            }
+        }
+    }
+}
+
+/// The content of the `#[prost(...)]` attributes we care about
+#[derive(Default)]
+struct ProstAttribute {
+    tag: Option<u32>,
+    tags: Vec<u32>,
+    oneof: Option<String>,
+    /// The enumeration type of the field or of the map values
+    enumeration: Option<String>,
+    optional: bool,
+    repeated: bool,
+    map: bool,
+}
+
+impl ProstAttribute {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut attribute = Self::default();
+        for attr in attrs {
+            if !attr.path().is_ident("prost") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag") {
+                    attribute.tag = Some(
+                        meta.value()?
+                            .parse::<LitStr>()?
+                            .value()
+                            .parse()
+                            .map_err(|e| meta.error(format!("invalid tag: {e}")))?,
+                    );
+                } else if meta.path.is_ident("tags") {
+                    attribute.tags = meta
+                        .value()?
+                        .parse::<LitStr>()?
+                        .value()
+                        .split(',')
+                        .map(|tag| tag.trim().parse())
+                        .collect::<std::result::Result<_, _>>()
+                        .map_err(|e| meta.error(format!("invalid tags: {e}")))?;
+                } else if meta.path.is_ident("oneof") {
+                    attribute.oneof = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("enumeration") {
+                    attribute.enumeration = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("map") {
+                    // e.g. `map = "string, enumeration(Role)"`
+                    let map = meta.value()?.parse::<LitStr>()?.value();
+                    attribute.map = true;
+                    attribute.enumeration = map
+                        .split_once(',')
+                        .and_then(|(_, value)| value.trim().strip_prefix("enumeration("))
+                        .and_then(|value| value.strip_suffix(')'))
+                        .map(Into::into);
+                } else if meta.path.is_ident("optional") {
+                    attribute.optional = true;
+                } else if meta.path.is_ident("repeated") {
+                    attribute.repeated = true;
+                } else {
+                    skip_meta_value(&meta)?;
+                }
+                Ok(())
+            })
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        }
+        Ok(attribute)
+    }
+
+    /// The `Debug` expression of an enumeration field printing the known values by name, `None` for other fields
+    fn enumeration_debug(&self, value: TokenStream) -> Result<Option<TokenStream>> {
+        let Some(enumeration) = &self.enumeration else {
+            return Ok(None);
+        };
+        let enumeration = syn::parse_str::<syn::Path>(enumeration)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Some(if self.map {
+            quote! {
+                debug_fn(|f| f.debug_map().entries(#value.iter().map(|(k, v)| (k, debug_fn(move |f| enumeration::<#enumeration>(*v, f))))).finish())
+            }
+        } else if self.repeated {
+            quote! {
+                debug_fn(|f| f.debug_list().entries(#value.iter().map(|v| debug_fn(move |f| enumeration::<#enumeration>(*v, f)))).finish())
+            }
+        } else if self.optional {
+            quote! {
+                #value.map(|v| debug_fn(move |f| enumeration::<#enumeration>(v, f)))
+            }
+        } else {
+            quote! {
+                debug_fn(|f| enumeration::<#enumeration>(#value, f))
+            }
+        }))
+    }
+}
+
+fn skip_meta_value(meta: &syn::meta::ParseNestedMeta<'_>) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_fields_and_oneof_variants() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Login {
+                #[prost(string, tag = "1")]
+                pub user: ::prost::alloc::string::String,
+                #[prost(string, tag = "2")]
+                pub r#password: ::prost::alloc::string::String,
+                #[prost(oneof = "login::Token", tags = "3, 4, 5")]
+                pub token: ::core::option::Option<login::Token>,
+                #[prost(enumeration = "Role", tag = "6")]
+                pub role: i32,
+            }
+            pub mod login {
+                #[derive(Clone, PartialEq, ::prost::Oneof)]
+                pub enum Token {
+                    #[prost(string, tag = "3")]
+                    ApiKey(::prost::alloc::string::String),
+                    #[prost(uint64, tag = "4")]
+                    Session(u64),
+                    #[prost(enumeration = "super::Role", tag = "5")]
+                    Role(i32),
+                }
+            }
+            impl ::prost::Name for Login {
+                const NAME: &'static str = "Login";
+                const PACKAGE: &'static str = "test";
+                fn full_name() -> ::prost::alloc::string::String {
+                    "test.Login".into()
+                }
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Other {
+                #[prost(string, tag = "2")]
+                pub password: ::prost::alloc::string::String,
+            }
+        "#;
+        let redacted = RedactedFields::from([("test.Login".into(), HashSet::from([2, 3]))]);
+        let output = redact_debug_in_file(input, &redacted).unwrap();
+
+        assert_eq!(
+            output.matches("#[prost(skip_debug)]").count(),
+            2,
+            "{output}"
+        );
+        assert!(
+            output.contains(r#".field("password", &::core::format_args!("[REDACTED]"))"#),
+            "{output}"
+        );
+        assert!(output.contains(r#".field("user", &self.user)"#), "{output}");
+        assert!(output.contains("Self::ApiKey(_) =>"), "{output}");
+        assert!(output.contains(r#"Self::Session(value) =>"#), "{output}");
+        assert!(
+            output.contains("enumeration::<Role>(self.role, f)"),
+            "{output}"
+        );
+        assert!(
+            output.contains("enumeration::<super::Role>(*value, f)"),
+            "{output}"
+        );
+        assert_eq!(
+            output.matches("impl ::core::fmt::Debug").count(),
+            2,
+            "{output}"
+        );
+    }
+}
@@ -0,0 +1,741 @@
+//! Generation of a TypeScript Twirp client from a [`FileDescriptorSet`].
+//!
+//! Messages are mapped to interfaces following the [protobuf JSON mapping](https://protobuf.dev/programming-guides/json/)
+//! and services to classes calling a `TwirpTransport`.
+
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    FileDescriptorSet, SourceCodeInfo,
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const RUNTIME: &str = r#"// Code generated by twurst-build. DO NOT EDIT.
+/* eslint-disable */
+
+/** Twirp error codes, see https://twitchtv.github.io/twirp/docs/spec_v7.html#error-codes */
+export type TwirpErrorCode =
+  | "canceled"
+  | "unknown"
+  | "invalid_argument"
+  | "malformed"
+  | "deadline_exceeded"
+  | "not_found"
+  | "bad_route"
+  | "already_exists"
+  | "permission_denied"
+  | "unauthenticated"
+  | "resource_exhausted"
+  | "failed_precondition"
+  | "aborted"
+  | "out_of_range"
+  | "unimplemented"
+  | "internal"
+  | "unavailable"
+  | "dataloss";
+
+/** Error returned by Twirp calls */
+export class TwirpError extends Error {
+  readonly code: TwirpErrorCode;
+  readonly meta: Record<string, string>;
+
+  constructor(code: TwirpErrorCode, message: string, meta: Record<string, string> = {}) {
+    super(message);
+    this.name = "TwirpError";
+    this.code = code;
+    this.meta = meta;
+  }
+}
+
+/** Options of a single Twirp call */
+export interface TwirpCallOptions {
+  /** Extra HTTP headers */
+  headers?: Record<string, string>;
+  /** Signal to abort the call */
+  signal?: AbortSignal;
+}
+
+/** Sends Twirp requests, implemented by {@link TwirpFetchTransport} */
+export interface TwirpTransport {
+  call<I, O>(path: string, request: I, options?: TwirpCallOptions): Promise<O>;
+}
+
+/** Options of {@link TwirpFetchTransport} */
+export interface TwirpFetchTransportOptions {
+  /** Base URL including the Twirp prefix, e.g. `https://example.com/twirp` */
+  baseUrl: string;
+  /** HTTP headers added to all requests */
+  headers?: Record<string, string>;
+  /** Custom `fetch` implementation, the global one by default */
+  fetch?: typeof fetch;
+}
+
+/** {@link TwirpTransport} sending JSON requests using `fetch` */
+export class TwirpFetchTransport implements TwirpTransport {
+  private readonly baseUrl: string;
+  private readonly headers: Record<string, string>;
+  private readonly fetch: typeof fetch;
+
+  constructor(options: TwirpFetchTransportOptions) {
+    this.baseUrl = options.baseUrl.replace(/\/$/, "");
+    this.headers = options.headers ?? {};
+    this.fetch = options.fetch ?? globalThis.fetch.bind(globalThis);
+  }
+
+  async call<I, O>(path: string, request: I, options: TwirpCallOptions = {}): Promise<O> {
+    let response: Response;
+    try {
+      response = await this.fetch(this.baseUrl + path, {
+        method: "POST",
+        headers: { ...this.headers, ...options.headers, "Content-Type": "application/json" },
+        body: JSON.stringify(request),
+        signal: options.signal,
+      });
+    } catch (e) {
+      throw new TwirpError("unknown", `Transport error during the request: ${e}`);
+    }
+    if (response.status === 200) {
+      return (await response.json()) as O;
+    }
+    const body = await response.text();
+    let error: { code?: unknown; msg?: unknown; meta?: unknown } | undefined;
+    try {
+      error = JSON.parse(body);
+    } catch {
+      error = undefined;
+    }
+    if (error && typeof error.code === "string" && typeof error.msg === "string") {
+      throw new TwirpError(
+        error.code as TwirpErrorCode,
+        error.msg,
+        (error.meta ?? {}) as Record<string, string>,
+      );
+    }
+    throw new TwirpError(twirpErrorCodeFromHttpStatus(response.status), body || `HTTP error ${response.status}`);
+  }
+}
+
+function twirpErrorCodeFromHttpStatus(status: number): TwirpErrorCode {
+  switch (status) {
+    case 401:
+      return "unauthenticated";
+    case 403:
+      return "permission_denied";
+    case 404:
+      return "bad_route";
+    case 429:
+    case 502:
+    case 503:
+    case 504:
+      return "unavailable";
+    default:
+      return status >= 300 && status < 400 ? "internal" : "unknown";
+  }
+}
+"#;
+
+/// Generates the TypeScript client for all the messages and services of the descriptor set.
+pub fn generate_typescript(file_descriptor_set: &FileDescriptorSet) -> String {
+    let types = TypeNames::new(file_descriptor_set);
+    let mut output = RUNTIME.to_string();
+    for file in &file_descriptor_set.file {
+        let comments = Comments::new(file.source_code_info.as_ref());
+        let prefix = match file.package() {
+            "" => String::new(),
+            package => format!(".{package}"),
+        };
+        for (i, enum_type) in file.enum_type.iter().enumerate() {
+            write_enum(
+                &mut output,
+                &types,
+                &comments,
+                &[5, i as i32],
+                &prefix,
+                enum_type,
+            );
+        }
+        for (i, message) in file.message_type.iter().enumerate() {
+            write_message(
+                &mut output,
+                &types,
+                &comments,
+                &[4, i as i32],
+                &prefix,
+                message,
+            );
+        }
+        write_services(&mut output, &types, &comments, file);
+    }
+    output
+}
+
+fn write_message(
+    output: &mut String,
+    types: &TypeNames,
+    comments: &Comments,
+    path: &[i32],
+    prefix: &str,
+    message: &DescriptorProto,
+) {
+    let full_name = format!("{prefix}.{}", message.name());
+    if message.options.as_ref().is_some_and(|o| o.map_entry()) || types.is_well_known(&full_name) {
+        return;
+    }
+    output.push('\n');
+    comments.write(output, path, "");
+    let name = types.name(&full_name);
+    if message.field.is_empty() {
+        let _ = writeln!(output, "export interface {name} {{}}");
+    } else {
+        let _ = writeln!(output, "export interface {name} {{");
+        for (i, field) in message.field.iter().enumerate() {
+            comments.write(output, &[path, &[2, i as i32]].concat(), "  ");
+            let _ = writeln!(
+                output,
+                "  {}?: {};",
+                json_name(field),
+                field_type(types, field)
+            );
+        }
+        output.push_str("}\n");
+    }
+    for (i, enum_type) in message.enum_type.iter().enumerate() {
+        write_enum(
+            output,
+            types,
+            comments,
+            &[path, &[4, i as i32]].concat(),
+            &full_name,
+            enum_type,
+        );
+    }
+    for (i, nested) in message.nested_type.iter().enumerate() {
+        write_message(
+            output,
+            types,
+            comments,
+            &[path, &[3, i as i32]].concat(),
+            &full_name,
+            nested,
+        );
+    }
+}
+
+fn write_enum(
+    output: &mut String,
+    types: &TypeNames,
+    comments: &Comments,
+    path: &[i32],
+    prefix: &str,
+    enum_type: &EnumDescriptorProto,
+) {
+    let full_name = format!("{prefix}.{}", enum_type.name());
+    if types.is_well_known(&full_name) {
+        return;
+    }
+    output.push('\n');
+    comments.write(output, path, "");
+    let values = enum_type
+        .value
+        .iter()
+        .map(|v| format!("\"{}\"", v.name()))
+        .collect::<Vec<_>>();
+    let _ = writeln!(
+        output,
+        "export type {} = {};",
+        types.name(&full_name),
+        if values.is_empty() {
+            "never".into()
+        } else {
+            values.join(" | ")
+        }
+    );
+}
+
+fn write_services(
+    output: &mut String,
+    types: &TypeNames,
+    comments: &Comments,
+    file: &FileDescriptorProto,
+) {
+    for (i, service) in file.service.iter().enumerate() {
+        let service_path = [6, i as i32];
+        output.push('\n');
+        comments.write(output, &service_path, "");
+        let service_name = match file.package() {
+            "" => service.name().to_string(),
+            package => format!("{package}.{}", service.name()),
+        };
+        let _ = writeln!(
+            output,
+            "export class {} {{",
+            types.name(&format!(".{service_name}"))
+        );
+        output.push_str("  constructor(private readonly transport: TwirpTransport) {}\n");
+        for (j, method) in service.method.iter().enumerate() {
+            // Twirp does not support streaming
+            if method.client_streaming() || method.server_streaming() {
+                continue;
+            }
+            let input_type = types.resolve(method.input_type());
+            let output_type = types.resolve(method.output_type());
+            output.push('\n');
+            comments.write(output, &[6, i as i32, 2, j as i32], "  ");
+            let _ = writeln!(
+                output,
+                "  {}(request: {input_type}, options?: TwirpCallOptions): Promise<{output_type}> {{",
+                lower_camel_case(method.name())
+            );
+            let _ = writeln!(
+                output,
+                "    return this.transport.call(\"/{service_name}/{}\", request, options);",
+                method.name()
+            );
+            output.push_str("  }\n");
+        }
+        output.push_str("}\n");
+    }
+}
+
+fn field_type(types: &TypeNames, field: &FieldDescriptorProto) -> String {
+    if let Some(value) = types.map_value(field.type_name()) {
+        // JSON object keys are always strings
+        return format!("{{ [key: string]: {} }}", field_type(types, value));
+    }
+    let item_type = match field.r#type() {
+        Type::Double
+        | Type::Float
+        | Type::Int32
+        | Type::Uint32
+        | Type::Sint32
+        | Type::Fixed32
+        | Type::Sfixed32 => "number".into(),
+        // 64 bits integers are encoded as strings to avoid precision loss
+        Type::Int64
+        | Type::Uint64
+        | Type::Sint64
+        | Type::Fixed64
+        | Type::Sfixed64
+        | Type::String
+        | Type::Bytes => "string".into(),
+        Type::Bool => "boolean".into(),
+        Type::Enum | Type::Message | Type::Group => types.resolve(field.type_name()),
+    };
+    if field.label() == Label::Repeated {
+        if item_type.contains(' ') {
+            format!("({item_type})[]")
+        } else {
+            format!("{item_type}[]")
+        }
+    } else {
+        item_type
+    }
+}
+
+fn json_name(field: &FieldDescriptorProto) -> String {
+    field
+        .json_name
+        .clone()
+        .unwrap_or_else(|| lower_camel_case(field.name()))
+}
+
+/// Converts a `snake_case` or `PascalCase` name to `lowerCamelCase`
+fn lower_camel_case(name: &str) -> String {
+    let mut output = String::with_capacity(name.len());
+    let mut upper_next = false;
+    for (i, c) in name.chars().enumerate() {
+        if c == '_' {
+            upper_next = true;
+        } else if i == 0 {
+            output.extend(c.to_lowercase());
+        } else if upper_next {
+            output.extend(c.to_uppercase());
+            upper_next = false;
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// Well known types with a special JSON mapping
+fn well_known_type(full_name: &str) -> Option<&'static str> {
+    Some(match full_name {
+        ".google.protobuf.Any" => "{ \"@type\": string; [key: string]: unknown }",
+        ".google.protobuf.Timestamp"
+        | ".google.protobuf.Duration"
+        | ".google.protobuf.FieldMask"
+        | ".google.protobuf.StringValue"
+        | ".google.protobuf.BytesValue"
+        | ".google.protobuf.Int64Value"
+        | ".google.protobuf.UInt64Value" => "string",
+        ".google.protobuf.DoubleValue"
+        | ".google.protobuf.FloatValue"
+        | ".google.protobuf.Int32Value"
+        | ".google.protobuf.UInt32Value" => "number",
+        ".google.protobuf.BoolValue" => "boolean",
+        ".google.protobuf.Struct" => "{ [key: string]: unknown }",
+        ".google.protobuf.Value" => "unknown",
+        ".google.protobuf.ListValue" => "unknown[]",
+        ".google.protobuf.NullValue" => "null",
+        ".google.protobuf.Empty" => "{}",
+        _ => return None,
+    })
+}
+
+/// TypeScript names of all the messages, enums and service clients.
+///
+/// Nested types are named `Parent_Child`, clients `ServiceClient`
+/// and the package is only added when two types or clients would get the same name.
+struct TypeNames<'a> {
+    names: HashMap<String, String>,
+    map_values: HashMap<String, &'a FieldDescriptorProto>,
+}
+
+impl<'a> TypeNames<'a> {
+    fn new(file_descriptor_set: &'a FileDescriptorSet) -> Self {
+        // (full name, package, local name)
+        let mut types = Vec::new();
+        let mut map_values = HashMap::new();
+        for file in &file_descriptor_set.file {
+            let package = file.package();
+            let package_prefix = match package {
+                "" => String::new(),
+                package => format!(".{package}"),
+            };
+            for enum_type in &file.enum_type {
+                types.push((
+                    format!("{package_prefix}.{}", enum_type.name()),
+                    package,
+                    enum_type.name().to_string(),
+                ));
+            }
+            for message in &file.message_type {
+                Self::add_message(
+                    &mut types,
+                    &mut map_values,
+                    package,
+                    &package_prefix,
+                    "",
+                    message,
+                );
+            }
+            for service in &file.service {
+                types.push((
+                    format!("{package_prefix}.{}", service.name()),
+                    package,
+                    format!("{}Client", service.name()),
+                ));
+            }
+        }
+        let mut name_counts = HashMap::<&str, usize>::new();
+        for (_, _, local_name) in &types {
+            *name_counts.entry(local_name).or_default() += 1;
+        }
+        let names = types
+            .iter()
+            .map(|(full_name, package, local_name)| {
+                let name = if name_counts[local_name.as_str()] > 1 && !package.is_empty() {
+                    format!("{}_{local_name}", package.replace('.', "_"))
+                } else {
+                    local_name.clone()
+                };
+                (full_name.clone(), name)
+            })
+            .collect();
+        Self { names, map_values }
+    }
+
+    fn add_message(
+        types: &mut Vec<(String, &'a str, String)>,
+        map_values: &mut HashMap<String, &'a FieldDescriptorProto>,
+        package: &'a str,
+        prefix: &str,
+        local_prefix: &str,
+        message: &'a DescriptorProto,
+    ) {
+        let full_name = format!("{prefix}.{}", message.name());
+        if message.options.as_ref().is_some_and(|o| o.map_entry()) {
+            if let [_, value] = message.field.as_slice() {
+                map_values.insert(full_name, value);
+            }
+            return;
+        }
+        let local_name = format!("{local_prefix}{}", message.name());
+        for enum_type in &message.enum_type {
+            types.push((
+                format!("{full_name}.{}", enum_type.name()),
+                package,
+                format!("{local_name}_{}", enum_type.name()),
+            ));
+        }
+        for nested in &message.nested_type {
+            Self::add_message(
+                types,
+                map_values,
+                package,
+                &full_name,
+                &format!("{local_name}_"),
+                nested,
+            );
+        }
+        types.push((full_name, package, local_name));
+    }
+
+    fn is_well_known(&self, full_name: &str) -> bool {
+        well_known_type(full_name).is_some()
+    }
+
+    fn name(&self, full_name: &str) -> &str {
+        &self.names[full_name]
+    }
+
+    fn resolve(&self, full_name: &str) -> String {
+        if let Some(well_known) = well_known_type(full_name) {
+            return well_known.into();
+        }
+        self.names
+            .get(full_name)
+            .cloned()
+            // The type is not in the descriptor set, we do not fail the build for it
+            .unwrap_or_else(|| "unknown".into())
+    }
+
+    /// The value field if the type is a map entry
+    fn map_value(&self, full_name: &str) -> Option<&'a FieldDescriptorProto> {
+        self.map_values.get(full_name).copied()
+    }
+}
+
+/// Leading comments of the elements of a file, indexed by their source code info path
+struct Comments(HashMap<Vec<i32>, String>);
+
+impl Comments {
+    fn new(source_code_info: Option<&SourceCodeInfo>) -> Self {
+        Self(
+            source_code_info
+                .into_iter()
+                .flat_map(|info| &info.location)
+                .filter_map(|location| {
+                    let comments = location.leading_comments().trim();
+                    (!comments.is_empty()).then(|| (location.path.clone(), comments.into()))
+                })
+                .collect(),
+        )
+    }
+
+    fn write(&self, output: &mut String, path: &[i32], indent: &str) {
+        let Some(comments) = self.0.get(path) else {
+            return;
+        };
+        let _ = writeln!(output, "{indent}/**");
+        for line in comments.lines() {
+            let line = line.trim_end().replace("*/", "*\\/");
+            if line.is_empty() {
+                let _ = writeln!(output, "{indent} *");
+            } else {
+                let _ = writeln!(
+                    output,
+                    "{indent} *{}{line}",
+                    if line.starts_with(' ') { "" } else { " " }
+                );
+            }
+        }
+        let _ = writeln!(output, "{indent} */");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::source_code_info::Location;
+    use prost_types::{
+        EnumValueDescriptorProto, MessageOptions, MethodDescriptorProto, ServiceDescriptorProto,
+    };
+
+    fn field(
+        name: &str,
+        r#type: Type,
+        type_name: Option<&str>,
+        label: Label,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.into()),
+            r#type: Some(r#type.into()),
+            type_name: type_name.map(Into::into),
+            label: Some(label.into()),
+            ..Default::default()
+        }
+    }
+
+    fn file(package: &str, message: DescriptorProto) -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some(format!("{package}.proto")),
+            package: Some(package.into()),
+            message_type: vec![message],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn generates_interfaces_and_clients() {
+        let mut foo = file(
+            "foo",
+            DescriptorProto {
+                name: Some("Request".into()),
+                field: vec![
+                    field("user_id", Type::Int64, None, Label::Optional),
+                    field(
+                        "tags",
+                        Type::Message,
+                        Some(".foo.Request.TagsEntry"),
+                        Label::Repeated,
+                    ),
+                    field(
+                        "kinds",
+                        Type::Enum,
+                        Some(".foo.Request.Kind"),
+                        Label::Repeated,
+                    ),
+                    field(
+                        "other",
+                        Type::Message,
+                        Some(".bar.Request"),
+                        Label::Optional,
+                    ),
+                ],
+                nested_type: vec![DescriptorProto {
+                    name: Some("TagsEntry".into()),
+                    field: vec![
+                        field("key", Type::String, None, Label::Optional),
+                        field("value", Type::Int32, None, Label::Optional),
+                    ],
+                    options: Some(MessageOptions {
+                        map_entry: Some(true),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("Kind".into()),
+                    value: vec![
+                        EnumValueDescriptorProto {
+                            name: Some("KIND_UNSPECIFIED".into()),
+                            number: Some(0),
+                            ..Default::default()
+                        },
+                        EnumValueDescriptorProto {
+                            name: Some("KIND_A".into()),
+                            number: Some(1),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+        foo.service = vec![ServiceDescriptorProto {
+            name: Some("FooService".into()),
+            method: vec![
+                MethodDescriptorProto {
+                    name: Some("GetThing".into()),
+                    input_type: Some(".foo.Request".into()),
+                    output_type: Some(".google.protobuf.Timestamp".into()),
+                    ..Default::default()
+                },
+                MethodDescriptorProto {
+                    name: Some("Stream".into()),
+                    input_type: Some(".foo.Request".into()),
+                    output_type: Some(".foo.Request".into()),
+                    server_streaming: Some(true),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }];
+        foo.source_code_info = Some(SourceCodeInfo {
+            location: vec![Location {
+                path: vec![6, 0, 2, 0],
+                leading_comments: Some(" Gets a thing\n".into()),
+                ..Default::default()
+            }],
+        });
+        let bar = file(
+            "bar",
+            DescriptorProto {
+                name: Some("Request".into()),
+                ..Default::default()
+            },
+        );
+
+        let output = generate_typescript(&FileDescriptorSet {
+            file: vec![foo, bar],
+        });
+        let generated = &output[RUNTIME.len()..];
+        assert_eq!(
+            generated,
+            r#"
+export interface foo_Request {
+  userId?: string;
+  tags?: { [key: string]: number };
+  kinds?: Request_Kind[];
+  other?: bar_Request;
+}
+
+export type Request_Kind = "KIND_UNSPECIFIED" | "KIND_A";
+
+export class FooServiceClient {
+  constructor(private readonly transport: TwirpTransport) {}
+
+  /**
+   * Gets a thing
+   */
+  getThing(request: foo_Request, options?: TwirpCallOptions): Promise<string> {
+    return this.transport.call("/foo.FooService/GetThing", request, options);
+  }
+}
+
+export interface bar_Request {}
+"#
+        );
+    }
+
+    #[test]
+    fn qualifies_clients_of_services_with_the_same_name() {
+        let service = |package: &str| {
+            let mut file = file(
+                package,
+                DescriptorProto {
+                    name: Some("Request".into()),
+                    ..Default::default()
+                },
+            );
+            file.service = vec![ServiceDescriptorProto {
+                name: Some("Service".into()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Call".into()),
+                    input_type: Some(format!(".{package}.Request")),
+                    output_type: Some(format!(".{package}.Request")),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }];
+            file
+        };
+
+        let output = generate_typescript(&FileDescriptorSet {
+            file: vec![service("foo.v1"), service("bar")],
+        });
+        assert!(
+            output.contains("export class foo_v1_ServiceClient {"),
+            "{output}"
+        );
+        assert!(
+            output.contains("export class bar_ServiceClient {"),
+            "{output}"
+        );
+        assert!(!output.contains("export class ServiceClient"), "{output}");
+    }
+}
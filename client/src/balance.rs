@@ -0,0 +1,647 @@
+//! Client-side load balancing across multiple Twirp endpoints.
+//!
+//! [`BalancedService`] wraps any [`TwirpHttpService`] and sends each request to one of a set of base URLs.
+//! The set of base URLs is provided by a [`Resolver`] and refreshed periodically.
+//! Endpoints that keep failing (transport errors or `503 Service Unavailable` responses, i.e. Twirp `unavailable` errors)
+//! are ejected for some time.
+//!
+//! It plugs into generated clients unchanged:
+//! ```
+//! use std::convert::Infallible;
+//! use twurst_client::TwirpHttpClient;
+//! use twurst_client::balance::{BalanceStrategy, BalancedService};
+//!
+//! let transport = tower::service_fn(|_request| async {
+//!     Ok::<_, Infallible>(http::Response::new(String::new()))
+//! }); // Use here your favorite transport, e.g. `Reqwest013Service`
+//! let _client = TwirpHttpClient::new(
+//!     BalancedService::new(
+//!         transport,
+//!         ["http://replica-1:8080/twirp", "http://replica-2:8080/twirp"],
+//!     )
+//!     .strategy(BalanceStrategy::PowerOfTwoChoices),
+//! );
+//! ```
+//! Note that the [`TwirpHttpClient`](crate::TwirpHttpClient) must not have a base URL, the balancer sets it.
+
+use crate::{TwirpHttpService, TwirpRequestBody};
+use http::uri::PathAndQuery;
+use http::{Request, Response, StatusCode, Uri};
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Provides the list of base URLs a [`BalancedService`] balances between.
+///
+/// Base URLs follow the same grammar as the `base_url` of [`TwirpHttpClient`](crate::TwirpHttpClient),
+/// i.e. `http://example.com/twirp`.
+pub trait Resolver: Send + Sync + 'static {
+    /// Returns the current list of base URLs.
+    fn resolve(&self) -> impl Future<Output = io::Result<Vec<String>>> + Send;
+}
+
+/// [`Resolver`] returning a fixed list of base URLs.
+#[derive(Clone, Debug)]
+pub struct StaticResolver(Vec<String>);
+
+impl StaticResolver {
+    /// Resolver always returning `base_urls`
+    pub fn new(base_urls: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self(base_urls.into_iter().map(Into::into).collect())
+    }
+}
+
+impl Resolver for StaticResolver {
+    async fn resolve(&self) -> io::Result<Vec<String>> {
+        Ok(self.0.clone())
+    }
+}
+
+/// [`Resolver`] looking up all the IP addresses of a host name using the system DNS resolver.
+///
+/// For example `DnsResolver::new("http", "my-service.internal", 8080, "/twirp")` returns `http://10.0.0.1:8080/twirp`, `http://10.0.0.2:8080/twirp`...
+/// Note that the requests `Host` header is set to the IP address and not to the host name.
+#[derive(Clone, Debug)]
+pub struct DnsResolver {
+    scheme: String,
+    host: String,
+    port: u16,
+    prefix: String,
+}
+
+impl DnsResolver {
+    /// Resolver building a base URL `{scheme}://{address}:{port}{prefix}` for each IP address of `host`
+    pub fn new(
+        scheme: impl Into<String>,
+        host: impl Into<String>,
+        port: u16,
+        prefix: impl Into<String>,
+    ) -> Self {
+        let mut prefix = prefix.into();
+        // We remove the last '/' to make concatenation work
+        if prefix.ends_with('/') {
+            prefix.pop();
+        }
+        Self {
+            scheme: scheme.into(),
+            host: host.into(),
+            port,
+            prefix,
+        }
+    }
+}
+
+impl Resolver for DnsResolver {
+    async fn resolve(&self) -> io::Result<Vec<String>> {
+        let mut base_urls = tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await?
+            .map(|address| format!("{}://{address}{}", self.scheme, self.prefix))
+            .collect::<Vec<_>>();
+        base_urls.sort();
+        base_urls.dedup();
+        Ok(base_urls)
+    }
+}
+
+/// [`Resolver`] reading the base URLs from a file, one per line.
+///
+/// Empty lines and lines starting with `#` are ignored.
+/// The file is read again on each refresh so it can be updated while the application is running.
+#[derive(Clone, Debug)]
+pub struct FileResolver(PathBuf);
+
+impl FileResolver {
+    /// Resolver reading the file at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self(path.into())
+    }
+}
+
+impl Resolver for FileResolver {
+    async fn resolve(&self) -> io::Result<Vec<String>> {
+        Ok(tokio::fs::read_to_string(&self.0)
+            .await?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Into::into)
+            .collect())
+    }
+}
+
+/// How a [`BalancedService`] picks an endpoint for each request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BalanceStrategy {
+    /// Use each available endpoint in turn
+    #[default]
+    RoundRobin,
+    /// Pick two available endpoints at random and use the one with the fewest in-flight requests
+    PowerOfTwoChoices,
+}
+
+/// A [`TwirpHttpService`] balancing requests between multiple base URLs.
+///
+/// See the [module documentation](self) for more details.
+pub struct BalancedService<S, R = StaticResolver> {
+    shared: Arc<Shared<S, R>>,
+    config: BalanceConfig,
+}
+
+struct Shared<S, R> {
+    service: S,
+    resolver: R,
+    endpoints: RwLock<Vec<Arc<Endpoint>>>,
+    // time of the last call to the resolver, successful or not
+    last_refresh: Mutex<Option<Instant>>,
+    // held while the resolver is called so that concurrent requests do not call it again
+    refresh_lock: tokio::sync::Mutex<()>,
+    counter: AtomicUsize,
+    random_state: AtomicU64,
+}
+
+#[derive(Clone, Copy)]
+struct BalanceConfig {
+    strategy: BalanceStrategy,
+    refresh_interval: Duration,
+    eject_after: u32,
+    eject_for: Duration,
+}
+
+struct Endpoint {
+    base_url: String,
+    in_flight: AtomicUsize,
+    consecutive_failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+impl<S: TwirpHttpService + Sync> BalancedService<S, StaticResolver> {
+    /// Balances requests between a fixed list of base URLs.
+    pub fn new(service: S, base_urls: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::with_resolver(service, StaticResolver::new(base_urls))
+    }
+}
+
+impl<S: TwirpHttpService + Sync, R: Resolver> BalancedService<S, R> {
+    /// Balances requests between the base URLs returned by a [`Resolver`].
+    ///
+    /// The resolver is called before the first request and then every 30 seconds by default.
+    pub fn with_resolver(service: S, resolver: R) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                service,
+                resolver,
+                endpoints: RwLock::new(Vec::new()),
+                last_refresh: Mutex::new(None),
+                refresh_lock: tokio::sync::Mutex::new(()),
+                counter: AtomicUsize::new(0),
+                random_state: AtomicU64::new(RandomState::new().hash_one(0) | 1),
+            }),
+            config: BalanceConfig {
+                strategy: BalanceStrategy::RoundRobin,
+                refresh_interval: Duration::from_secs(30),
+                eject_after: 5,
+                eject_for: Duration::from_secs(30),
+            },
+        }
+    }
+
+    /// Sets the strategy used to pick an endpoint (round-robin by default).
+    pub fn strategy(mut self, strategy: BalanceStrategy) -> Self {
+        self.config.strategy = strategy;
+        self
+    }
+
+    /// Sets how often the [`Resolver`] is called to refresh the list of endpoints (30 seconds by default).
+    ///
+    /// A failed resolution is only retried after the same interval, the previous endpoints are kept meanwhile.
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.config.refresh_interval = interval;
+        self
+    }
+
+    /// Ejects an endpoint for `duration` after `consecutive_failures` failures in a row (5 failures and 30 seconds by default).
+    ///
+    /// Failures are transport errors and responses with status `502`, `503` (the Twirp `unavailable` error) and `504`.
+    pub fn eject_after(mut self, consecutive_failures: u32, duration: Duration) -> Self {
+        self.config.eject_after = consecutive_failures;
+        self.config.eject_for = duration;
+        self
+    }
+
+    /// Calls the [`Resolver`] now and updates the list of endpoints.
+    ///
+    /// Statistics about endpoints that are still present are kept.
+    pub async fn refresh(&self) -> io::Result<()> {
+        let _guard = self.shared.refresh_lock.lock().await;
+        self.do_refresh().await
+    }
+
+    /// Must be called while holding `refresh_lock`
+    async fn do_refresh(&self) -> io::Result<()> {
+        // The attempt is recorded even if it fails to not call a failing resolver on every request
+        *self.shared.last_refresh.lock().unwrap() = Some(Instant::now());
+        let base_urls = self.shared.resolver.resolve().await?;
+        let mut endpoints = self.shared.endpoints.write().unwrap();
+        let new_endpoints = base_urls
+            .into_iter()
+            .map(|mut base_url| {
+                // We remove the last '/' to make concatenation work
+                if base_url.ends_with('/') {
+                    base_url.pop();
+                }
+                endpoints
+                    .iter()
+                    .find(|e| e.base_url == base_url)
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        Arc::new(Endpoint {
+                            base_url,
+                            in_flight: AtomicUsize::new(0),
+                            consecutive_failures: AtomicU32::new(0),
+                            ejected_until: Mutex::new(None),
+                        })
+                    })
+            })
+            .collect();
+        *endpoints = new_endpoints;
+        Ok(())
+    }
+
+    /// The base URLs currently used, excluding the ejected ones.
+    pub fn available_base_urls(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.shared
+            .endpoints
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|e| e.is_available(now))
+            .map(|e| e.base_url.clone())
+            .collect()
+    }
+
+    fn needs_refresh(&self) -> bool {
+        self.shared
+            .last_refresh
+            .lock()
+            .unwrap()
+            .is_none_or(|last| last.elapsed() >= self.config.refresh_interval)
+    }
+
+    async fn refresh_if_needed(&self) -> io::Result<()> {
+        if !self.needs_refresh() {
+            return Ok(());
+        }
+        let _guard = self.shared.refresh_lock.lock().await;
+        // Another request might have refreshed the endpoints while we were waiting for the lock
+        if !self.needs_refresh() {
+            return Ok(());
+        }
+        match self.do_refresh().await {
+            Ok(()) => Ok(()),
+            // We keep using the previous endpoints if the resolution fails
+            Err(_) if !self.shared.endpoints.read().unwrap().is_empty() => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn pick_endpoint(&self) -> Option<Arc<Endpoint>> {
+        let now = Instant::now();
+        let available = self
+            .shared
+            .endpoints
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|e| e.is_available(now))
+            .cloned()
+            .collect::<Vec<_>>();
+        if available.is_empty() {
+            return None;
+        }
+        let index = match self.config.strategy {
+            BalanceStrategy::RoundRobin => {
+                self.shared.counter.fetch_add(1, Ordering::Relaxed) % available.len()
+            }
+            BalanceStrategy::PowerOfTwoChoices => {
+                let first = self.random_index(available.len());
+                let second = self.random_index(available.len());
+                if available[second].in_flight.load(Ordering::Relaxed)
+                    < available[first].in_flight.load(Ordering::Relaxed)
+                {
+                    second
+                } else {
+                    first
+                }
+            }
+        };
+        Some(available[index].clone())
+    }
+
+    fn random_index(&self, len: usize) -> usize {
+        // xorshift64, good enough to pick endpoints
+        let mut x = self.shared.random_state.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.shared.random_state.store(x, Ordering::Relaxed);
+        (x % len as u64) as usize
+    }
+
+    fn record_outcome(&self, endpoint: &Endpoint, failure: bool) {
+        if !failure {
+            endpoint.consecutive_failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        let failures = endpoint
+            .consecutive_failures
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        if failures >= self.config.eject_after {
+            endpoint.consecutive_failures.store(0, Ordering::Relaxed);
+            *endpoint.ejected_until.lock().unwrap() = Some(Instant::now() + self.config.eject_for);
+        }
+    }
+}
+
+impl<S, R> Clone for BalancedService<S, R> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            config: self.config,
+        }
+    }
+}
+
+impl Endpoint {
+    fn is_available(&self, now: Instant) -> bool {
+        self.ejected_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| until <= now)
+    }
+}
+
+/// Decrements the in-flight counter even if the request future is dropped
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<S: TwirpHttpService + Sync, R: Resolver> TwirpHttpService for BalancedService<S, R> {
+    type ResponseBody = S::ResponseBody;
+    type Error = BalanceError<S::Error>;
+
+    async fn ready(&self) -> Result<(), Self::Error> {
+        self.refresh_if_needed()
+            .await
+            .map_err(BalanceError::Resolver)?;
+        self.shared
+            .service
+            .ready()
+            .await
+            .map_err(BalanceError::Service)
+    }
+
+    async fn call(
+        &self,
+        request: Request<TwirpRequestBody>,
+    ) -> Result<Response<Self::ResponseBody>, Self::Error> {
+        let endpoint = self.pick_endpoint().ok_or(BalanceError::NoEndpoint)?;
+        let (mut parts, body) = request.into_parts();
+        let path = parts.uri.path_and_query().map_or("/", PathAndQuery::as_str);
+        parts.uri = Uri::try_from(format!("{}{path}", endpoint.base_url))
+            .map_err(|e| BalanceError::InvalidBaseUrl(endpoint.base_url.clone(), e))?;
+
+        endpoint.in_flight.fetch_add(1, Ordering::Relaxed);
+        let _guard = InFlightGuard(&endpoint.in_flight);
+        let result = self
+            .shared
+            .service
+            .call(Request::from_parts(parts, body))
+            .await;
+        let failure = match &result {
+            Ok(response) => matches!(
+                response.status(),
+                StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Err(_) => true,
+        };
+        self.record_outcome(&endpoint, failure);
+        result.map_err(BalanceError::Service)
+    }
+}
+
+/// Error returned by [`BalancedService`].
+#[derive(Debug)]
+pub enum BalanceError<E> {
+    /// All endpoints are ejected or the resolver returned no endpoint
+    NoEndpoint,
+    /// The resolver failed and no endpoint is known
+    Resolver(io::Error),
+    /// A base URL is not valid
+    InvalidBaseUrl(String, http::uri::InvalidUri),
+    /// The wrapped service failed
+    Service(E),
+}
+
+impl<E: fmt::Display> fmt::Display for BalanceError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoEndpoint => f.write_str("No endpoint available"),
+            Self::Resolver(e) => write!(f, "Failed to resolve the endpoints: {e}"),
+            Self::InvalidBaseUrl(base_url, e) => write!(f, "Invalid base URL {base_url}: {e}"),
+            Self::Service(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for BalanceError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::NoEndpoint => None,
+            Self::Resolver(e) => Some(e),
+            Self::InvalidBaseUrl(_, e) => Some(e),
+            Self::Service(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TwirpError, TwirpErrorCode, TwirpHttpClient};
+    use prost_reflect::prost_types::Timestamp;
+    use std::convert::Infallible;
+    use tower::service_fn;
+
+    fn recording_service(
+        calls: Arc<Mutex<Vec<String>>>,
+        unavailable_host: &'static str,
+    ) -> impl TwirpHttpService<ResponseBody = String, Error = Infallible> + Sync {
+        service_fn(move |request: Request<TwirpRequestBody>| {
+            let calls = calls.clone();
+            async move {
+                let uri = request.uri().to_string();
+                calls.lock().unwrap().push(uri.clone());
+                if uri.contains(unavailable_host) {
+                    return Ok(TwirpError::unavailable("down").into());
+                }
+                Ok(TwirpError::not_found("ok").into())
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn round_robin() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let client = TwirpHttpClient::new(BalancedService::new(
+            recording_service(calls.clone(), "none"),
+            ["http://a/twirp", "http://b/twirp/"],
+        ));
+        for _ in 0..4 {
+            let error = client
+                .call::<_, Timestamp>("/foo", &Timestamp::default())
+                .await
+                .unwrap_err();
+            assert_eq!(error.code(), TwirpErrorCode::NotFound);
+        }
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "http://a/twirp/foo",
+                "http://b/twirp/foo",
+                "http://a/twirp/foo",
+                "http://b/twirp/foo"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn power_of_two_choices() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let client = TwirpHttpClient::new(
+            BalancedService::new(
+                recording_service(calls.clone(), "none"),
+                ["http://a/twirp", "http://b/twirp"],
+            )
+            .strategy(BalanceStrategy::PowerOfTwoChoices),
+        );
+        for _ in 0..10 {
+            client
+                .call::<_, Timestamp>("/foo", &Timestamp::default())
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(calls.lock().unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn ejects_unavailable_endpoints() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let service = BalancedService::new(
+            recording_service(calls.clone(), "http://b/"),
+            ["http://a/twirp", "http://b/twirp"],
+        )
+        .eject_after(2, Duration::from_secs(60));
+        let client = TwirpHttpClient::new(service.clone());
+        for _ in 0..6 {
+            client
+                .call::<_, Timestamp>("/foo", &Timestamp::default())
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(service.available_base_urls(), ["http://a/twirp"]);
+        assert_eq!(
+            calls
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|uri| uri.starts_with("http://b/"))
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn no_endpoint() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let client = TwirpHttpClient::new(BalancedService::new(
+            recording_service(calls, "none"),
+            Vec::<String>::new(),
+        ));
+        let error = client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Transport error during the request: No endpoint available"
+        );
+    }
+
+    #[tokio::test]
+    async fn file_resolver_refresh() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("replicas.txt");
+        std::fs::write(&path, "# replicas\nhttp://a/twirp\n\n")?;
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let service = BalancedService::with_resolver(
+            recording_service(calls, "none"),
+            FileResolver::new(&path),
+        );
+        service.refresh().await?;
+        assert_eq!(service.available_base_urls(), ["http://a/twirp"]);
+        std::fs::write(&path, "http://a/twirp\nhttp://b/twirp\n")?;
+        service.refresh().await?;
+        assert_eq!(
+            service.available_base_urls(),
+            ["http://a/twirp", "http://b/twirp"]
+        );
+        Ok(())
+    }
+
+    /// Resolver counting its calls and failing
+    struct FailingResolver(Arc<AtomicUsize>);
+
+    impl Resolver for FailingResolver {
+        async fn resolve(&self) -> io::Result<Vec<String>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            tokio::task::yield_now().await;
+            Err(io::Error::other("resolution failed"))
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_resolution_is_not_retried_on_each_request() {
+        let resolutions = Arc::new(AtomicUsize::new(0));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let client = TwirpHttpClient::new(BalancedService::with_resolver(
+            recording_service(calls, "none"),
+            FailingResolver(resolutions.clone()),
+        ));
+        let request = Timestamp::default();
+        let call = || client.call::<_, Timestamp>("/foo", &request);
+        let (first, second) = tokio::join!(call(), call());
+        assert_eq!(
+            first.unwrap_err().message(),
+            "Service is not ready: Failed to resolve the endpoints: resolution failed"
+        );
+        // The other requests do not call the resolver again
+        second.unwrap_err();
+        call().await.unwrap_err();
+        assert_eq!(resolutions.load(Ordering::Relaxed), 1);
+    }
+}
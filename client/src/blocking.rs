@@ -0,0 +1,147 @@
+//! Blocking Twirp client for synchronous code.
+//!
+//! [`BlockingTwirpHttpClient`] wraps a [`TwirpHttpClient`] and runs its calls on an internal
+//! single-threaded Tokio runtime, so no async runtime has to be set up by the caller.
+//! The encoding, the error handling and the client settings are the ones of the wrapped client.
+//!
+//! ```
+//! # #[cfg(feature = "hyper-1")]
+//! # fn main() -> std::io::Result<()> {
+//! use twurst_client::TwirpHttpClient;
+//! use twurst_client::blocking::BlockingTwirpHttpClient;
+//!
+//! let _client =
+//!     BlockingTwirpHttpClient::new(TwirpHttpClient::new_using_hyper_1("http://example.com/twirp"))?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "hyper-1"))]
+//! # fn main() {}
+//! ```
+//!
+//! Generated clients get a blocking variant with `TwirpBuilder::with_blocking_client()` in `twurst-build`.
+//!
+//! Note that the blocking methods must not be called from an async context: they panic inside a Tokio runtime.
+
+use crate::{TwirpCallBuilder, TwirpHttpClient, TwirpHttpService, TwirpResponse};
+use prost_reflect::ReflectMessage;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use tokio::runtime::{Builder, Runtime};
+use twurst_error::TwirpError;
+
+/// Blocking wrapper of a [`TwirpHttpClient`], see the [module documentation](self).
+///
+/// Clones share the same runtime.
+#[derive(Clone)]
+pub struct BlockingTwirpHttpClient<S: TwirpHttpService> {
+    client: TwirpHttpClient<S>,
+    runtime: Arc<Runtime>,
+}
+
+impl<S: TwirpHttpService> BlockingTwirpHttpClient<S> {
+    /// Wraps the client, building the internal runtime.
+    pub fn new(client: impl Into<TwirpHttpClient<S>>) -> io::Result<Self> {
+        Ok(Self {
+            client: client.into(),
+            runtime: Arc::new(Builder::new_current_thread().enable_all().build()?),
+        })
+    }
+
+    /// The wrapped async client
+    pub fn inner(&self) -> &TwirpHttpClient<S> {
+        &self.client
+    }
+
+    /// Send a Twirp request and get a response.
+    ///
+    /// Used internally by the generated code.
+    pub fn call<I: ReflectMessage, O: ReflectMessage + Default>(
+        &self,
+        path: &str,
+        request: &I,
+    ) -> Result<O, TwirpError> {
+        self.block_on(self.client.call(path, request))
+    }
+
+    /// Like [`call`](Self::call) but also returns the response metadata (status, headers and extensions).
+    pub fn call_with_metadata<I: ReflectMessage, O: ReflectMessage + Default>(
+        &self,
+        path: &str,
+        request: &I,
+    ) -> Result<TwirpResponse<O>, TwirpError> {
+        self.block_on(self.client.call_builder(path, request).send_with_metadata())
+    }
+
+    /// Sends a call customized with [`TwirpHttpClient::call_builder`] (headers, timeout, encoding...).
+    ///
+    /// ```
+    /// # #[cfg(feature = "hyper-1")]
+    /// # fn main() -> std::io::Result<()> {
+    /// use prost_reflect::prost_types::Timestamp;
+    /// use twurst_client::TwirpHttpClient;
+    /// use twurst_client::blocking::BlockingTwirpHttpClient;
+    ///
+    /// let client =
+    ///     BlockingTwirpHttpClient::new(TwirpHttpClient::new_using_hyper_1("http://example.com/twirp"))?;
+    /// let request = Timestamp::default();
+    /// let call = || -> Result<Timestamp, _> {
+    ///     client.send(
+    ///         client
+    ///             .inner()
+    ///             .call_builder("/example.ExampleService/Test", &request)
+    ///             .header("x-request-id", "abc-123"),
+    ///     )
+    /// };
+    /// # let _ = call;
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(feature = "hyper-1"))]
+    /// # fn main() {}
+    /// ```
+    pub fn send<I: ReflectMessage, O: ReflectMessage + Default>(
+        &self,
+        builder: TwirpCallBuilder<'_, S, I>,
+    ) -> Result<O, TwirpError> {
+        self.block_on(builder.send())
+    }
+
+    /// Runs a future on the internal runtime, e.g. a call built from [`inner`](Self::inner).
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::MyMessage;
+    use http::{Request, Response};
+    use http_body_util::Full;
+    use prost::Message;
+    use prost_reflect::bytes::Bytes;
+    use std::convert::Infallible;
+    use tower::service_fn;
+
+    #[test]
+    fn call_without_runtime() -> Result<(), Box<dyn std::error::Error>> {
+        let client = BlockingTwirpHttpClient::new(TwirpHttpClient::new(service_fn(
+            |request: Request<_>| async move {
+                assert_eq!(request.uri(), "/foo");
+                // The runtime timer is available to the service
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header("Content-Type", "application/protobuf")
+                        .body(Full::new(Bytes::from(MyMessage {}.encode_to_vec())))
+                        .unwrap(),
+                )
+            },
+        )))?;
+        let response: MyMessage = client.call("/foo", &MyMessage {})?;
+        assert_eq!(response, MyMessage {});
+        let response = client.call_with_metadata::<_, MyMessage>("/foo", &MyMessage {})?;
+        assert_eq!(response.status(), 200);
+        Ok(())
+    }
+}
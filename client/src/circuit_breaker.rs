@@ -0,0 +1,231 @@
+use crate::{TwirpError, TwirpErrorCode};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Circuit breaker tracking the failure rate of each Twirp method.
+///
+/// Registered with [`TwirpHttpClient::set_circuit_breaker`](crate::TwirpHttpClient::set_circuit_breaker).
+/// The state is kept per method path (e.g. `/example.ExampleService/Test`) so a broken method does not block the other ones.
+///
+/// - When closed, calls are sent and their outcomes recorded in a sliding window.
+///   If the failure rate in the window reaches the threshold, the circuit opens.
+/// - When open, calls fail immediately with [`TwirpErrorCode::Unavailable`] and the `circuit_open` meta set to `true`.
+/// - After the open duration, the circuit becomes half-open and a few probe calls are allowed.
+///   If they all succeed the circuit closes, if one fails it opens again.
+///
+/// By default, transport errors and the [`Unavailable`](TwirpErrorCode::Unavailable), [`Unknown`](TwirpErrorCode::Unknown),
+/// [`Internal`](TwirpErrorCode::Internal) and [`DeadlineExceeded`](TwirpErrorCode::DeadlineExceeded) error codes are failures.
+///
+/// ```
+/// use std::time::Duration;
+/// use twurst_client::CircuitBreaker;
+///
+/// let _breaker = CircuitBreaker::new()
+///     .failure_rate_threshold(0.5)
+///     .window_size(20)
+///     .minimum_calls(10)
+///     .open_duration(Duration::from_secs(30))
+///     .half_open_probes(2);
+/// ```
+pub struct CircuitBreaker {
+    failure_rate_threshold: f64,
+    window_size: usize,
+    minimum_calls: usize,
+    open_duration: Duration,
+    half_open_probes: u32,
+    failure_codes: Vec<TwirpErrorCode>,
+    routes: Mutex<HashMap<String, Circuit>>,
+}
+
+enum Circuit {
+    Closed { outcomes: VecDeque<bool> },
+    Open { until: Instant },
+    HalfOpen { in_flight: u32, successes: u32 },
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreaker {
+    /// Circuit breaker with the default settings.
+    ///
+    /// The circuit opens when at least half of the last 20 calls failed, with at least 10 calls in the window.
+    /// It stays open for 30 seconds, then a single successful probe call closes it.
+    pub fn new() -> Self {
+        Self {
+            failure_rate_threshold: 0.5,
+            window_size: 20,
+            minimum_calls: 10,
+            open_duration: Duration::from_secs(30),
+            half_open_probes: 1,
+            failure_codes: vec![
+                TwirpErrorCode::Unavailable,
+                TwirpErrorCode::Unknown,
+                TwirpErrorCode::Internal,
+                TwirpErrorCode::DeadlineExceeded,
+            ],
+            routes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Failure rate (between 0 and 1) above which the circuit opens (0.5 by default)
+    pub fn failure_rate_threshold(mut self, threshold: f64) -> Self {
+        self.failure_rate_threshold = threshold;
+        self
+    }
+
+    /// Number of most recent calls used to compute the failure rate (20 by default)
+    pub fn window_size(mut self, size: usize) -> Self {
+        self.window_size = size.max(1);
+        self
+    }
+
+    /// Minimal number of calls in the window before the circuit can open (10 by default)
+    pub fn minimum_calls(mut self, calls: usize) -> Self {
+        self.minimum_calls = calls;
+        self
+    }
+
+    /// How long the circuit stays open before allowing probe calls (30 seconds by default)
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.open_duration = duration;
+        self
+    }
+
+    /// Number of probe calls that must succeed in half-open state to close the circuit (1 by default)
+    pub fn half_open_probes(mut self, probes: u32) -> Self {
+        self.half_open_probes = probes.max(1);
+        self
+    }
+
+    /// Error codes counted as failures
+    ///
+    /// Transport errors are returned with the [`TwirpErrorCode::Unknown`] code.
+    pub fn failure_codes(mut self, codes: impl IntoIterator<Item = TwirpErrorCode>) -> Self {
+        self.failure_codes = codes.into_iter().collect();
+        self
+    }
+
+    /// Checks if a call to `path` is allowed and reserves a probe slot when half-open
+    pub(crate) fn acquire<'a>(&'a self, path: &'a str) -> Result<CircuitPermit<'a>, TwirpError> {
+        let mut routes = self.routes.lock().unwrap();
+        let circuit = routes
+            .entry(path.to_string())
+            .or_insert_with(|| Circuit::Closed {
+                outcomes: VecDeque::new(),
+            });
+        let probe = match circuit {
+            Circuit::Closed { .. } => false,
+            Circuit::Open { until } if *until <= Instant::now() => {
+                *circuit = Circuit::HalfOpen {
+                    in_flight: 1,
+                    successes: 0,
+                };
+                true
+            }
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } if *in_flight + *successes < self.half_open_probes => {
+                *in_flight += 1;
+                true
+            }
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => {
+                return Err(TwirpError::unavailable(format!(
+                    "The circuit breaker is open for {path}"
+                ))
+                .with_meta("circuit_open", "true"));
+            }
+        };
+        Ok(CircuitPermit {
+            breaker: self,
+            path,
+            probe,
+            recorded: false,
+        })
+    }
+
+    fn is_failure(&self, error: &TwirpError) -> bool {
+        self.failure_codes.contains(&error.code())
+    }
+
+    fn record(&self, path: &str, probe: bool, failure: Option<bool>) {
+        let mut routes = self.routes.lock().unwrap();
+        let Some(circuit) = routes.get_mut(path) else {
+            return;
+        };
+        match circuit {
+            Circuit::Closed { outcomes } => {
+                let Some(failure) = failure else {
+                    return;
+                };
+                outcomes.push_back(failure);
+                while outcomes.len() > self.window_size {
+                    outcomes.pop_front();
+                }
+                let failures = outcomes.iter().filter(|f| **f).count();
+                if outcomes.len() >= self.minimum_calls
+                    && failures as f64 >= self.failure_rate_threshold * outcomes.len() as f64
+                {
+                    *circuit = Circuit::Open {
+                        until: Instant::now() + self.open_duration,
+                    };
+                }
+            }
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } if probe => match failure {
+                Some(true) => {
+                    *circuit = Circuit::Open {
+                        until: Instant::now() + self.open_duration,
+                    };
+                }
+                Some(false) => {
+                    *in_flight -= 1;
+                    *successes += 1;
+                    if *successes >= self.half_open_probes {
+                        *circuit = Circuit::Closed {
+                            outcomes: VecDeque::new(),
+                        };
+                    }
+                }
+                // The probe has been cancelled, we free its slot
+                None => *in_flight -= 1,
+            },
+            // Outcome of a call started before the circuit state changed
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => (),
+        }
+    }
+}
+
+/// Authorization to send a call, used to record its outcome
+pub(crate) struct CircuitPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    path: &'a str,
+    probe: bool,
+    recorded: bool,
+}
+
+impl CircuitPermit<'_> {
+    pub(crate) fn record<T>(mut self, result: &Result<T, TwirpError>) {
+        self.recorded = true;
+        let failure = result
+            .as_ref()
+            .err()
+            .is_some_and(|e| self.breaker.is_failure(e));
+        self.breaker.record(self.path, self.probe, Some(failure));
+    }
+}
+
+impl Drop for CircuitPermit<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breaker.record(self.path, self.probe, None);
+        }
+    }
+}
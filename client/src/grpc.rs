@@ -0,0 +1,214 @@
+use crate::{TwirpError, TwirpErrorCode};
+use http::uri::{PathAndQuery, Uri};
+use http_body::Body;
+use prost_reflect::ReflectMessage;
+use prost_reflect::bytes::Bytes;
+use std::error::Error;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_stream::Stream;
+use tonic::client::{Grpc, GrpcService};
+use tonic::metadata::MetadataValue;
+use tonic_prost::ProstCodec;
+use twurst_error::request_id::{self, REQUEST_ID_HEADER};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Builds a request forwarding the current request ID
+fn new_request<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    if let Some(request_id) = request_id::current()
+        && let Ok(value) = MetadataValue::try_from(request_id)
+    {
+        request.metadata_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    request
+}
+
+/// Underlying client used by autogenerated gRPC clients to handle networking.
+///
+/// It wraps a [`tonic::client::Grpc`] client and maps all errors to [`TwirpError`]
+/// so that the generated gRPC clients expose the same surface as the Twirp ones.
+///
+/// Can be constructed from any [`GrpcHttpService`] like [`tonic::transport::Channel`](https://docs.rs/tonic/latest/tonic/transport/struct.Channel.html)
+/// using [`GrpcClient::new`] or [`GrpcClient::with_origin`].
+#[derive(Clone)]
+pub struct GrpcClient<T> {
+    grpc: Grpc<T>,
+}
+
+impl<T: GrpcHttpService> GrpcClient<T> {
+    /// Builds a new client from a gRPC service.
+    ///
+    /// The request URIs are sent as is, this is fine for [`tonic::transport::Channel`](https://docs.rs/tonic/latest/tonic/transport/struct.Channel.html)
+    /// that sets the origin itself.
+    pub fn new(service: T) -> Self {
+        Self {
+            grpc: Grpc::new(service),
+        }
+    }
+
+    /// Builds a new client from a gRPC service that needs the request URIs to be absolute.
+    ///
+    /// The `origin` scheme and authority are added to all requests.
+    pub fn with_origin(service: T, origin: Uri) -> Self {
+        Self {
+            grpc: Grpc::with_origin(service, origin),
+        }
+    }
+
+    /// Send a unary gRPC request and get a response.
+    ///
+    /// Used internally by the generated code.
+    pub async fn unary<
+        I: ReflectMessage + Clone + Sync + 'static,
+        O: ReflectMessage + Default + Sync + 'static,
+    >(
+        &self,
+        path: &str,
+        request: &I,
+    ) -> Result<O, TwirpError> {
+        let (mut grpc, path) = self.prepare(path).await?;
+        Ok(grpc
+            .unary(new_request(request.clone()), path, ProstCodec::default())
+            .await?
+            .into_inner())
+    }
+
+    /// Send a server streaming gRPC request and get a stream of responses.
+    ///
+    /// Used internally by the generated code.
+    pub async fn server_streaming<
+        I: ReflectMessage + Clone + Sync + 'static,
+        O: ReflectMessage + Default + Sync + 'static,
+    >(
+        &self,
+        path: &str,
+        request: &I,
+    ) -> Result<GrpcStream<O>, TwirpError> {
+        let (mut grpc, path) = self.prepare(path).await?;
+        let stream = grpc
+            .server_streaming(new_request(request.clone()), path, ProstCodec::default())
+            .await?
+            .into_inner();
+        Ok(GrpcStream { stream })
+    }
+
+    /// Send a client streaming gRPC request and get a response.
+    ///
+    /// Used internally by the generated code.
+    pub async fn client_streaming<
+        I: ReflectMessage + Sync + 'static,
+        O: ReflectMessage + Default + Sync + 'static,
+    >(
+        &self,
+        path: &str,
+        request: impl Stream<Item = I> + Send + 'static,
+    ) -> Result<O, TwirpError> {
+        let (mut grpc, path) = self.prepare(path).await?;
+        Ok(grpc
+            .client_streaming(new_request(request), path, ProstCodec::default())
+            .await?
+            .into_inner())
+    }
+
+    /// Send a bidirectional streaming gRPC request and get a stream of responses.
+    ///
+    /// Used internally by the generated code.
+    pub async fn streaming<
+        I: ReflectMessage + Sync + 'static,
+        O: ReflectMessage + Default + Sync + 'static,
+    >(
+        &self,
+        path: &str,
+        request: impl Stream<Item = I> + Send + 'static,
+    ) -> Result<GrpcStream<O>, TwirpError> {
+        let (mut grpc, path) = self.prepare(path).await?;
+        let stream = grpc
+            .streaming(new_request(request), path, ProstCodec::default())
+            .await?
+            .into_inner();
+        Ok(GrpcStream { stream })
+    }
+
+    async fn prepare(&self, path: &str) -> Result<(Grpc<T>, PathAndQuery), TwirpError> {
+        let path = PathAndQuery::try_from(path).map_err(|e| {
+            TwirpError::wrap(
+                TwirpErrorCode::Malformed,
+                format!("Invalid gRPC method path {path}: {e}"),
+                e,
+            )
+        })?;
+        let mut grpc = self.grpc.clone();
+        // We ensure that the service is ready
+        grpc.ready().await.map_err(|e| {
+            let e = e.into();
+            TwirpError::wrap(
+                TwirpErrorCode::Unknown,
+                format!("Service is not ready: {e}"),
+                tonic::Status::from_error(e),
+            )
+        })?;
+        Ok((grpc, path))
+    }
+}
+
+impl<T: GrpcHttpService> From<T> for GrpcClient<T> {
+    #[inline]
+    fn from(service: T) -> Self {
+        Self::new(service)
+    }
+}
+
+/// A service that can be used to send gRPC requests eg. a [`tonic::transport::Channel`](https://docs.rs/tonic/latest/tonic/transport/struct.Channel.html).
+///
+/// Used by [`GrpcClient`] to handle HTTP/2.
+/// It is implemented for all [`tower::Service`](tower_service::Service) with the proper bounds.
+pub trait GrpcHttpService:
+    GrpcService<
+        tonic::body::Body,
+        Error: Into<BoxError>,
+        ResponseBody: Body<Data = Bytes, Error: Into<BoxError> + Send> + Send + 'static,
+        Future: Send,
+    > + Clone
+    + Send
+    + Sync
+    + 'static
+{
+}
+
+impl<
+    T: GrpcService<
+            tonic::body::Body,
+            Error: Into<BoxError>,
+            ResponseBody: Body<Data = Bytes, Error: Into<BoxError> + Send> + Send + 'static,
+            Future: Send,
+        > + Clone
+        + Send
+        + Sync
+        + 'static,
+> GrpcHttpService for T
+{
+}
+
+/// Stream of messages returned by server streaming gRPC methods.
+pub struct GrpcStream<O> {
+    stream: tonic::Streaming<O>,
+}
+
+impl<O> Stream for GrpcStream<O> {
+    type Item = Result<O, TwirpError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<O, TwirpError>>> {
+        Pin::new(&mut self.stream)
+            .poll_next(cx)
+            .map(|opt| opt.map(|r| Ok(r?)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
@@ -6,29 +6,73 @@
 )]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+#[cfg(feature = "balance")]
+pub mod balance;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod circuit_breaker;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "record")]
+pub mod record;
+#[cfg(all(unix, feature = "unix"))]
+mod unix;
+
+pub use circuit_breaker::CircuitBreaker;
+#[cfg(feature = "grpc")]
+pub use grpc::{GrpcClient, GrpcHttpService, GrpcStream};
 use http::header::CONTENT_TYPE;
-use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use http::{Extensions, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
 use http_body::{Body, Frame, SizeHint};
 use http_body_util::BodyExt;
+#[cfg(feature = "hyper-1")]
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+#[cfg(feature = "hyper-1")]
+use hyper_util::rt::{TokioExecutor, TokioTimer};
 use prost_reflect::bytes::{Buf, Bytes, BytesMut};
-use prost_reflect::{DeserializeOptions, DynamicMessage, ReflectMessage};
+use prost_reflect::{DeserializeOptions, DynamicMessage, MessageDescriptor, ReflectMessage};
+use request_id::REQUEST_ID_HEADER;
 use serde::Serialize;
 use std::convert::Infallible;
 use std::error::Error;
 use std::future::poll_fn;
+use std::marker::PhantomData;
 use std::mem::take;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+#[cfg(any(feature = "hyper-1", feature = "timeout"))]
+use std::time::Duration;
+use std::time::Instant;
 use tower_service::Service;
+pub use twurst_error::any::{AnyResolver, TypeUrlResolver, UnknownAny};
+pub use twurst_error::registry::DescriptorRegistry;
+pub use twurst_error::request_id;
 pub use twurst_error::{TwirpError, TwirpErrorCode};
+#[cfg(all(unix, feature = "unix"))]
+pub use unix::UnixConnector;
+
+#[doc(hidden)]
+#[cfg(feature = "grpc")]
+pub mod codegen {
+    pub use tokio_stream::Stream;
+}
 
 const APPLICATION_JSON: HeaderValue = HeaderValue::from_static("application/json");
 const APPLICATION_PROTOBUF: HeaderValue = HeaderValue::from_static("application/protobuf");
 
+/// Header used to advertise the call timeout to the server, in milliseconds.
+///
+/// It is set by [`TwirpCallBuilder::send`] when a timeout is configured,
+/// either with `TwirpCallBuilder::timeout` or `TwirpHttpClient::set_timeout` (`timeout` feature).
+pub const TIMEOUT_HEADER: HeaderName = HeaderName::from_static("twirp-timeout-ms");
+
 /// Underlying client used by autogenerated clients to handle networking.
 ///
 /// Can be constructed with [`TwirpHttpClient::new_using_reqwest_012`] to use [`reqwest 0.12`](reqwest_012),
 /// with [`TwirpHttpClient::new_using_reqwest_013`] to use [`reqwest 0.13`](reqwest_013),
+/// with [`TwirpHttpClient::new_using_hyper_1`] to use [`hyper 1`](hyper_1),
+/// with `TwirpHttpClient::new_using_unix_socket` to use a Unix domain socket (`unix` feature),
 /// or from a regular [`tower::Service`](Service) using [`TwirpHttpClient::new_with_base`]
 /// or [`TwirpHttpClient::new`] if relative URLs are fine.
 ///
@@ -42,6 +86,12 @@ pub struct TwirpHttpClient<S: TwirpHttpService> {
     service: S,
     base_url: Option<String>,
     use_json: bool,
+    #[cfg(feature = "timeout")]
+    timeout: Option<Duration>,
+    interceptors: Vec<Arc<dyn DynTwirpInterceptor>>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    descriptor_registry: Option<DescriptorRegistry>,
+    any_resolver: Option<AnyResolver>,
 }
 
 #[cfg(feature = "reqwest-012")]
@@ -112,6 +162,45 @@ impl TwirpHttpClient<Reqwest013Service> {
     }
 }
 
+#[cfg(feature = "hyper-1")]
+impl TwirpHttpClient<Hyper1Service> {
+    /// Builds a new client using [`hyper 1`](hyper_1) with the default configuration.
+    ///
+    /// Note that `base_url` must be absolute with a scheme like `http://`.
+    /// TLS is not supported by the default connector, use [`Hyper1ServiceBuilder::build`] with a TLS connector for `https://`.
+    ///
+    /// ```
+    /// use twurst_client::TwirpHttpClient;
+    ///
+    /// let _client = TwirpHttpClient::new_using_hyper_1("http://example.com/twirp");
+    /// ```
+    pub fn new_using_hyper_1(base_url: impl Into<String>) -> Self {
+        Self::new_with_hyper_1_service(Hyper1Service::new(), base_url)
+    }
+}
+
+#[cfg(feature = "hyper-1")]
+impl<C: Connect + Clone + Send + Sync + 'static> TwirpHttpClient<Hyper1Service<C>> {
+    /// Builds a new client using a custom [`hyper 1`](hyper_1) service built with [`Hyper1Service::builder`].
+    ///
+    /// Note that `base_url` must be absolute with a scheme like `https://`.
+    ///
+    /// ```
+    /// use twurst_client::{Hyper1Service, TwirpHttpClient};
+    ///
+    /// let _client = TwirpHttpClient::new_with_hyper_1_service(
+    ///     Hyper1Service::builder().http2_prior_knowledge().build_http(),
+    ///     "http://example.com/twirp",
+    /// );
+    /// ```
+    pub fn new_with_hyper_1_service(
+        service: Hyper1Service<C>,
+        base_url: impl Into<String>,
+    ) -> Self {
+        Self::new_with_base(service, base_url)
+    }
+}
+
 impl<S: TwirpHttpService> TwirpHttpClient<S> {
     /// Builds a new client from a [`tower::Service`](Service) and a base URL to the Twirp endpoint.
     ///
@@ -138,6 +227,12 @@ impl<S: TwirpHttpService> TwirpHttpClient<S> {
             service,
             base_url: Some(base_url),
             use_json: false,
+            #[cfg(feature = "timeout")]
+            timeout: None,
+            interceptors: Vec::new(),
+            circuit_breaker: None,
+            descriptor_registry: None,
+            any_resolver: None,
         }
     }
 
@@ -158,6 +253,12 @@ impl<S: TwirpHttpService> TwirpHttpClient<S> {
             service,
             base_url: None,
             use_json: false,
+            #[cfg(feature = "timeout")]
+            timeout: None,
+            interceptors: Vec::new(),
+            circuit_breaker: None,
+            descriptor_registry: None,
+            any_resolver: None,
         }
     }
 
@@ -171,6 +272,70 @@ impl<S: TwirpHttpService> TwirpHttpClient<S> {
         self.use_json = false;
     }
 
+    /// Set a default timeout applied to every call made with this client.
+    ///
+    /// It can be overridden per call with [`TwirpCallBuilder::timeout`].
+    /// Calls that do not complete in time fail with [`TwirpErrorCode::DeadlineExceeded`].
+    ///
+    /// Note that timeouts rely on the [`tokio`] timer and must be awaited within a Tokio runtime.
+    #[cfg(feature = "timeout")]
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Remove the default timeout set with [`Self::set_timeout`] (the default)
+    #[cfg(feature = "timeout")]
+    pub fn unset_timeout(&mut self) {
+        self.timeout = None;
+    }
+
+    /// Add an interceptor called on every call made with this client.
+    ///
+    /// Interceptors are called in the order they have been added.
+    /// See [`TwirpInterceptor`] for more details.
+    pub fn add_interceptor(&mut self, interceptor: impl TwirpInterceptor) {
+        self.interceptors.push(Arc::new(interceptor));
+    }
+
+    /// Set a circuit breaker tracking the failures of each Twirp method called with this client.
+    ///
+    /// Calls to a method whose circuit is open fail immediately with [`TwirpErrorCode::Unavailable`].
+    /// See [`CircuitBreaker`] for more details.
+    pub fn set_circuit_breaker(&mut self, circuit_breaker: CircuitBreaker) {
+        self.circuit_breaker = Some(Arc::new(circuit_breaker));
+    }
+
+    /// Remove the circuit breaker set with [`Self::set_circuit_breaker`] (the default)
+    pub fn unset_circuit_breaker(&mut self) {
+        self.circuit_breaker = None;
+    }
+
+    /// Set a descriptor registry used to resolve `google.protobuf.Any` types in JSON requests and responses.
+    ///
+    /// By default, only the types known by the descriptor pool of the request or response message are resolved.
+    /// See [`DescriptorRegistry`] for more details.
+    pub fn set_descriptor_registry(&mut self, registry: DescriptorRegistry) {
+        self.descriptor_registry = Some(registry);
+    }
+
+    /// Remove the descriptor registry set with [`Self::set_descriptor_registry`] (the default)
+    pub fn unset_descriptor_registry(&mut self) {
+        self.descriptor_registry = None;
+    }
+
+    /// Set a resolver for the `google.protobuf.Any` type URLs in JSON requests and responses.
+    ///
+    /// It also defines what to do with the `Any` payloads whose type is unknown.
+    /// See [`AnyResolver`] for more details.
+    pub fn set_any_resolver(&mut self, resolver: AnyResolver) {
+        self.any_resolver = Some(resolver);
+    }
+
+    /// Remove the resolver set with [`Self::set_any_resolver`] (the default)
+    pub fn unset_any_resolver(&mut self) {
+        self.any_resolver = None;
+    }
+
     /// Send a Twirp request and get a response.
     ///
     /// Used internally by the generated code.
@@ -216,39 +381,19 @@ impl<S: TwirpHttpService> TwirpHttpClient<S> {
         };
         TwirpCallBuilder {
             client: self,
+            path,
             request,
             builder: Request::builder().method(Method::POST).uri(uri),
-        }
-    }
-
-    fn encode_body<T: ReflectMessage>(&self, message: &T) -> Result<TwirpRequestBody, TwirpError> {
-        if self.use_json {
-            Ok(json_encode(message)?.into())
-        } else {
-            let mut buffer = BytesMut::with_capacity(message.encoded_len());
-            message.encode(&mut buffer).map_err(|e| {
-                TwirpError::wrap(
-                    TwirpErrorCode::Internal,
-                    format!("Failed to serialize to protobuf: {e}"),
-                    e,
-                )
-            })?;
-            Ok(Bytes::from(buffer).into())
-        }
-    }
-
-    fn content_type(&self) -> HeaderValue {
-        if self.use_json {
-            APPLICATION_JSON
-        } else {
-            APPLICATION_PROTOBUF
+            #[cfg(feature = "timeout")]
+            timeout: self.timeout,
+            use_json: self.use_json,
         }
     }
 
     async fn extract_response<T: ReflectMessage + Default>(
         &self,
         response: Response<S::ResponseBody>,
-    ) -> Result<T, TwirpError> {
+    ) -> Result<TwirpResponse<T>, TwirpError> {
         // We collect the body
         // TODO: size limit
         let (parts, body) = response.into_parts();
@@ -259,46 +404,102 @@ impl<S: TwirpHttpService> TwirpHttpClient<S> {
                 e,
             )
         })?;
-        let response = Response::from_parts(parts, body);
 
         // Error
-        if response.status() != StatusCode::OK {
-            return Err(response.map(|b| b.to_bytes()).into());
+        if parts.status != StatusCode::OK {
+            return Err(Response::from_parts(parts, body.to_bytes()).into());
         }
 
         // Success
-        let content_type = response.headers().get(CONTENT_TYPE).cloned();
-        let body = response.into_body();
-        if content_type == Some(APPLICATION_PROTOBUF) {
+        let content_type = parts.headers.get(CONTENT_TYPE);
+        let message = if content_type == Some(&APPLICATION_PROTOBUF) {
             T::decode(body.aggregate()).map_err(|e| {
                 TwirpError::wrap(
                     TwirpErrorCode::Malformed,
                     format!("Bad response binary protobuf encoding: {e}"),
                     e,
                 )
-            })
-        } else if content_type == Some(APPLICATION_JSON) {
-            json_decode(&body.to_bytes())
+            })?
+        } else if content_type == Some(&APPLICATION_JSON) {
+            json_decode(
+                &body.to_bytes(),
+                self.descriptor_registry.as_ref(),
+                self.any_resolver.as_ref(),
+            )?
         } else if let Some(content_type) = content_type {
-            Err(TwirpError::malformed(format!(
+            return Err(TwirpError::malformed(format!(
                 "Unsupported response content-type: {}",
                 String::from_utf8_lossy(content_type.as_bytes())
-            )))
+            )));
         } else {
-            Err(TwirpError::malformed("No content-type in the response"))
-        }
+            return Err(TwirpError::malformed("No content-type in the response"));
+        };
+        Ok(TwirpResponse { parts, message })
+    }
+}
+
+/// A successful Twirp response, returned by [`TwirpCallBuilder::send_with_metadata`].
+///
+/// Gives access to the HTTP response metadata (status, headers and extensions) alongside the decoded message.
+#[derive(Debug)]
+pub struct TwirpResponse<O> {
+    parts: http::response::Parts,
+    message: O,
+}
+
+impl<O> TwirpResponse<O> {
+    /// The HTTP status of the response
+    #[inline]
+    pub fn status(&self) -> StatusCode {
+        self.parts.status
+    }
+
+    /// The HTTP headers of the response, e.g. rate-limit headers or request IDs
+    #[inline]
+    pub fn headers(&self) -> &HeaderMap {
+        &self.parts.headers
+    }
+
+    /// The extensions of the response, set by the [`TwirpHttpService`] or its layers
+    #[inline]
+    pub fn extensions(&self) -> &Extensions {
+        &self.parts.extensions
+    }
+
+    /// The decoded response message
+    #[inline]
+    pub fn message(&self) -> &O {
+        &self.message
+    }
+
+    /// Returns the decoded response message, dropping the metadata
+    #[inline]
+    pub fn into_message(self) -> O {
+        self.message
+    }
+
+    /// Returns the HTTP response parts and the decoded message
+    #[inline]
+    pub fn into_parts(self) -> (http::response::Parts, O) {
+        (self.parts, self.message)
     }
 }
 
 /// Builder for a single Twirp call, returned by [`TwirpHttpClient::call_builder`].
 ///
-/// Allows per-call customization (currently extra HTTP headers) before dispatching
+/// Allows per-call customization (extra HTTP headers, timeout) before dispatching
 /// the request via [`Self::send`].
+///
+/// Dropping the future returned by [`Self::send`] cancels the call.
 #[must_use = "TwirpCallBuilder does nothing until `.send()` is awaited"]
 pub struct TwirpCallBuilder<'a, S: TwirpHttpService, I> {
     client: &'a TwirpHttpClient<S>,
+    path: &'a str,
     request: &'a I,
     builder: http::request::Builder,
+    #[cfg(feature = "timeout")]
+    timeout: Option<Duration>,
+    use_json: bool,
 }
 
 impl<'a, S: TwirpHttpService, I: ReflectMessage> TwirpCallBuilder<'a, S, I> {
@@ -327,41 +528,385 @@ impl<'a, S: TwirpHttpService, I: ReflectMessage> TwirpCallBuilder<'a, S, I> {
         self.builder.headers_mut()
     }
 
+    /// Set the timeout of this call, overriding the one set with [`TwirpHttpClient::set_timeout`].
+    ///
+    /// The timeout covers the whole call, from waiting for the service to be ready to decoding the response.
+    /// It is advertised to the server using the [`TIMEOUT_HEADER`] header.
+    /// If it is reached, [`Self::send`] fails with [`TwirpErrorCode::DeadlineExceeded`].
+    ///
+    /// Note that timeouts rely on the [`tokio`] timer and must be awaited within a Tokio runtime.
+    #[cfg(feature = "timeout")]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Use JSON for this call, overriding the encoding set on the [`TwirpHttpClient`].
+    pub fn use_json(mut self) -> Self {
+        self.use_json = true;
+        self
+    }
+
+    /// Use binary protobuf encoding for this call, overriding the encoding set on the [`TwirpHttpClient`].
+    pub fn use_binary_protobuf(mut self) -> Self {
+        self.use_json = false;
+        self
+    }
+
+    /// Fix the response type of the call, allowing to call [`TypedTwirpCallBuilder::send`] without type annotations.
+    ///
+    /// Used by the `<method>_builder` methods of the generated clients.
+    pub fn typed<O: ReflectMessage + Default>(self) -> TypedTwirpCallBuilder<'a, S, I, O> {
+        TypedTwirpCallBuilder {
+            inner: self,
+            output: PhantomData,
+        }
+    }
+
     /// Dispatch the configured Twirp call and decode the response.
     pub async fn send<O: ReflectMessage + Default>(self) -> Result<O, TwirpError> {
+        Ok(self.send_with_metadata().await?.into_message())
+    }
+
+    /// Dispatch the configured Twirp call and decode the response, keeping the response metadata.
+    ///
+    /// The returned [`TwirpResponse`] gives access to the response status, headers and extensions.
+    pub async fn send_with_metadata<O: ReflectMessage + Default>(
+        self,
+    ) -> Result<TwirpResponse<O>, TwirpError> {
+        let permit = self
+            .client
+            .circuit_breaker
+            .as_deref()
+            .map(|breaker| breaker.acquire(self.path))
+            .transpose()?;
+        #[cfg(feature = "timeout")]
+        let result = self.send_with_timeout().await;
+        #[cfg(not(feature = "timeout"))]
+        let result = self.do_send(None).await;
+        if let Some(permit) = permit {
+            permit.record(&result);
+        }
+        result
+    }
+
+    #[cfg(feature = "timeout")]
+    async fn send_with_timeout<O: ReflectMessage + Default>(
+        self,
+    ) -> Result<TwirpResponse<O>, TwirpError> {
+        let Some(timeout) = self.timeout else {
+            return self.do_send(None).await;
+        };
+        let deadline = Instant::now() + timeout;
+        tokio::time::timeout(timeout, self.do_send(Some(deadline)))
+            .await
+            .map_err(|_| {
+                TwirpError::deadline_exceeded(format!(
+                    "The call did not complete within {} ms",
+                    timeout.as_millis()
+                ))
+            })?
+    }
+
+    /// Sends the call, advertising the time left before `deadline` to the server
+    async fn do_send<O: ReflectMessage + Default>(
+        self,
+        deadline: Option<Instant>,
+    ) -> Result<TwirpResponse<O>, TwirpError> {
         let TwirpCallBuilder {
             client,
+            path,
             request,
             mut builder,
+            use_json,
+            ..
         } = self;
-        // We ensure that the service is ready
-        client.service.ready().await.map_err(|e| {
-            TwirpError::wrap(
-                TwirpErrorCode::Unknown,
-                format!("Service is not ready: {e}"),
-                e,
-            )
-        })?;
-        let body = client.encode_body(request)?;
+        client.ready().await?;
+        let body = encode_body(
+            request,
+            use_json,
+            client.descriptor_registry.as_ref(),
+            client.any_resolver.as_ref(),
+        )?;
         // Force-set Content-Type after any user-supplied headers so the framework value wins.
         if let Some(headers) = builder.headers_mut() {
-            headers.insert(CONTENT_TYPE, client.content_type());
+            headers.insert(CONTENT_TYPE, content_type(use_json));
+            // The time spent waiting for the service to be ready is not available to the server anymore
+            if let Some(deadline) = deadline {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                let timeout_ms = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+                headers.insert(TIMEOUT_HEADER, timeout_ms.into());
+            }
+            // Forwards the ID of the request being processed by the enclosing server if not explicitly set
+            if !headers.contains_key(REQUEST_ID_HEADER)
+                && let Some(request_id) = request_id::current()
+                && let Ok(value) = HeaderValue::try_from(request_id)
+            {
+                headers.insert(REQUEST_ID_HEADER, value);
+            }
+        }
+        let (parts, ()) = builder
+            .body(())
+            .map_err(|e| {
+                TwirpError::wrap(
+                    TwirpErrorCode::Malformed,
+                    format!("Failed to construct request: {e}"),
+                    e,
+                )
+            })?
+            .into_parts();
+        let mut replayed = false;
+        loop {
+            let error = match client.send_once(path, parts.clone(), body.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+            let mut replay = false;
+            for interceptor in &client.interceptors {
+                if interceptor.intercept_error(path, &error).await == InterceptorAction::Replay {
+                    replay = true;
+                }
+            }
+            if !replay || replayed {
+                return Err(error);
+            }
+            replayed = true;
+            client.ready().await?;
         }
-        let http_request = builder.body(body).map_err(|e| {
+    }
+}
+
+/// [`TwirpCallBuilder`] with a known response type, returned by the `<method>_builder` methods of the generated clients.
+///
+/// ```
+/// use http::Response;
+/// use prost_reflect::prost_types::Timestamp;
+/// use std::convert::Infallible;
+/// use twurst_client::{TwirpHttpClient, TypedTwirpCallBuilder};
+/// use twurst_error::TwirpError;
+///
+/// let client = TwirpHttpClient::new(tower::service_fn(|_request| async {
+///     Ok::<Response<String>, Infallible>(TwirpError::unimplemented("not implemented").into())
+/// }));
+/// let request = Timestamp::default();
+/// // Similar to what generated clients do
+/// let builder: TypedTwirpCallBuilder<'_, _, _, Timestamp> = client
+///     .call_builder("/example.ExampleService/Test", &request)
+///     .typed();
+/// let _pending = builder
+///     .header("x-request-id", "abc-123")
+///     .use_json()
+///     .send();
+/// ```
+#[must_use = "TypedTwirpCallBuilder does nothing until `.send()` is awaited"]
+pub struct TypedTwirpCallBuilder<'a, S: TwirpHttpService, I, O> {
+    inner: TwirpCallBuilder<'a, S, I>,
+    output: PhantomData<fn() -> O>,
+}
+
+impl<S: TwirpHttpService, I: ReflectMessage, O: ReflectMessage + Default>
+    TypedTwirpCallBuilder<'_, S, I, O>
+{
+    /// Add a header to the outgoing request, see [`TwirpCallBuilder::header`].
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        self.inner = self.inner.header(name, value);
+        self
+    }
+
+    /// Mutable access to the headers of the outgoing request, see [`TwirpCallBuilder::headers_mut`].
+    pub fn headers_mut(&mut self) -> Option<&mut HeaderMap> {
+        self.inner.headers_mut()
+    }
+
+    /// Set the timeout of this call, see [`TwirpCallBuilder::timeout`].
+    #[cfg(feature = "timeout")]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.inner = self.inner.timeout(timeout);
+        self
+    }
+
+    /// Use JSON for this call, overriding the encoding set on the [`TwirpHttpClient`].
+    pub fn use_json(mut self) -> Self {
+        self.inner = self.inner.use_json();
+        self
+    }
+
+    /// Use binary protobuf encoding for this call, overriding the encoding set on the [`TwirpHttpClient`].
+    pub fn use_binary_protobuf(mut self) -> Self {
+        self.inner = self.inner.use_binary_protobuf();
+        self
+    }
+
+    /// Dispatch the configured Twirp call and decode the response.
+    pub async fn send(self) -> Result<O, TwirpError> {
+        self.inner.send().await
+    }
+
+    /// Dispatch the configured Twirp call and decode the response, keeping the response metadata.
+    pub async fn send_with_metadata(self) -> Result<TwirpResponse<O>, TwirpError> {
+        self.inner.send_with_metadata().await
+    }
+}
+
+impl<S: TwirpHttpService> TwirpHttpClient<S> {
+    /// We ensure that the service is ready
+    async fn ready(&self) -> Result<(), TwirpError> {
+        self.service.ready().await.map_err(|e| {
             TwirpError::wrap(
-                TwirpErrorCode::Malformed,
-                format!("Failed to construct request: {e}"),
+                TwirpErrorCode::Unknown,
+                format!("Service is not ready: {e}"),
                 e,
             )
-        })?;
-        let response = client.service.call(http_request).await.map_err(|e| {
+        })
+    }
+
+    async fn send_once<O: ReflectMessage + Default>(
+        &self,
+        path: &str,
+        mut parts: http::request::Parts,
+        body: Bytes,
+    ) -> Result<TwirpResponse<O>, TwirpError> {
+        for interceptor in &self.interceptors {
+            interceptor
+                .intercept_request(path, &mut parts.headers)
+                .await?;
+        }
+        let http_request = Request::from_parts(parts, body.into());
+        let response = self.service.call(http_request).await.map_err(|e| {
             TwirpError::wrap(
                 TwirpErrorCode::Unknown,
                 format!("Transport error during the request: {e}"),
                 e,
             )
         })?;
-        client.extract_response(response).await
+        self.extract_response(response).await
+    }
+}
+
+/// Hook called by [`TwirpHttpClient`] around each call.
+///
+/// Interceptors are registered with [`TwirpHttpClient::add_interceptor`].
+/// They see the Twirp method path (e.g. `/example.ExampleService/Test`) and can:
+/// - mutate the request headers before the request is sent with [`Self::intercept_request`],
+/// - inspect the [`TwirpError`] returned by a call and ask for it to be replayed once with [`Self::intercept_error`].
+///
+/// For example, to refresh an authentication token when the server returns [`TwirpErrorCode::Unauthenticated`]:
+/// ```
+/// use http::header::AUTHORIZATION;
+/// use http::{HeaderMap, HeaderValue, Response};
+/// use std::convert::Infallible;
+/// use std::sync::Mutex;
+/// use twurst_client::{
+///     InterceptorAction, TwirpError, TwirpErrorCode, TwirpHttpClient, TwirpInterceptor,
+/// };
+///
+/// struct RefreshingAuth {
+///     token: Mutex<String>,
+/// }
+///
+/// impl TwirpInterceptor for RefreshingAuth {
+///     async fn intercept_request(
+///         &self,
+///         _path: &str,
+///         headers: &mut HeaderMap,
+///     ) -> Result<(), TwirpError> {
+///         let token = format!("Bearer {}", self.token.lock().unwrap());
+///         headers.insert(
+///             AUTHORIZATION,
+///             HeaderValue::try_from(token)
+///                 .map_err(|_| TwirpError::internal("Invalid token"))?,
+///         );
+///         Ok(())
+///     }
+///
+///     async fn intercept_error(&self, _path: &str, error: &TwirpError) -> InterceptorAction {
+///         if error.code() != TwirpErrorCode::Unauthenticated {
+///             return InterceptorAction::Return;
+///         }
+///         *self.token.lock().unwrap() = "refreshed".into(); // Fetch a new token here
+///         InterceptorAction::Replay
+///     }
+/// }
+///
+/// let mut client = TwirpHttpClient::new(tower::service_fn(|_request| async {
+///     Ok::<Response<String>, Infallible>(TwirpError::unimplemented("not implemented").into())
+/// }));
+/// client.add_interceptor(RefreshingAuth {
+///     token: Mutex::new("initial".into()),
+/// });
+/// ```
+pub trait TwirpInterceptor: Send + Sync + 'static {
+    /// Called before each request is sent, including replays.
+    ///
+    /// Returning an error aborts the request.
+    fn intercept_request(
+        &self,
+        path: &str,
+        headers: &mut HeaderMap,
+    ) -> impl Future<Output = Result<(), TwirpError>> + Send {
+        let _ = (path, headers);
+        async { Ok(()) }
+    }
+
+    /// Called when a call fails, either because of a transport error or because the server returned an error.
+    ///
+    /// If any interceptor returns [`InterceptorAction::Replay`], the call is sent again.
+    /// A call is replayed at most once.
+    fn intercept_error(
+        &self,
+        path: &str,
+        error: &TwirpError,
+    ) -> impl Future<Output = InterceptorAction> + Send {
+        let _ = (path, error);
+        async { InterceptorAction::Return }
+    }
+}
+
+/// What to do with a failed call, returned by [`TwirpInterceptor::intercept_error`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterceptorAction {
+    /// Return the error to the caller.
+    Return,
+    /// Send the call again.
+    Replay,
+}
+
+/// Object safe version of [`TwirpInterceptor`] used to store interceptors
+trait DynTwirpInterceptor: Send + Sync {
+    fn intercept_request<'a>(
+        &'a self,
+        path: &'a str,
+        headers: &'a mut HeaderMap,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TwirpError>> + Send + 'a>>;
+
+    fn intercept_error<'a>(
+        &'a self,
+        path: &'a str,
+        error: &'a TwirpError,
+    ) -> Pin<Box<dyn Future<Output = InterceptorAction> + Send + 'a>>;
+}
+
+impl<T: TwirpInterceptor> DynTwirpInterceptor for T {
+    fn intercept_request<'a>(
+        &'a self,
+        path: &'a str,
+        headers: &'a mut HeaderMap,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TwirpError>> + Send + 'a>> {
+        Box::pin(TwirpInterceptor::intercept_request(self, path, headers))
+    }
+
+    fn intercept_error<'a>(
+        &'a self,
+        path: &'a str,
+        error: &'a TwirpError,
+    ) -> Pin<Box<dyn Future<Output = InterceptorAction> + Send + 'a>> {
+        Box::pin(TwirpInterceptor::intercept_error(self, path, error))
     }
 }
 
@@ -456,23 +1001,75 @@ impl Body for TwirpRequestBody {
     }
 }
 
-fn json_encode<T: ReflectMessage>(message: &T) -> Result<Bytes, TwirpError> {
-    let mut serializer = serde_json::Serializer::new(Vec::new());
-    message
-        .transcode_to_dynamic()
-        .serialize(&mut serializer)
-        .map_err(|e| {
+fn encode_body<T: ReflectMessage>(
+    message: &T,
+    use_json: bool,
+    registry: Option<&DescriptorRegistry>,
+    any_resolver: Option<&AnyResolver>,
+) -> Result<Bytes, TwirpError> {
+    if use_json {
+        json_encode(message, registry, any_resolver)
+    } else {
+        let mut buffer = BytesMut::with_capacity(message.encoded_len());
+        message.encode(&mut buffer).map_err(|e| {
             TwirpError::wrap(
-                TwirpErrorCode::Malformed,
-                format!("Failed to serialize request to JSON: {e}"),
+                TwirpErrorCode::Internal,
+                format!("Failed to serialize to protobuf: {e}"),
                 e,
             )
         })?;
+        Ok(buffer.into())
+    }
+}
+
+fn content_type(use_json: bool) -> HeaderValue {
+    if use_json {
+        APPLICATION_JSON
+    } else {
+        APPLICATION_PROTOBUF
+    }
+}
+
+fn json_encode<T: ReflectMessage>(
+    message: &T,
+    registry: Option<&DescriptorRegistry>,
+    any_resolver: Option<&AnyResolver>,
+) -> Result<Bytes, TwirpError> {
+    let dynamic_message = match registry {
+        Some(registry) => registry.transcode_to_dynamic(message),
+        None => message.transcode_to_dynamic(),
+    };
+    let mut serializer = serde_json::Serializer::new(Vec::new());
+    match any_resolver {
+        Some(any_resolver) => any_resolver
+            .encode_json(&dynamic_message)
+            .and_then(|json| json.serialize(&mut serializer)),
+        None => dynamic_message.serialize(&mut serializer),
+    }
+    .map_err(|e| {
+        TwirpError::wrap(
+            TwirpErrorCode::Malformed,
+            format!("Failed to serialize request to JSON: {e}"),
+            e,
+        )
+    })?;
     Ok(serializer.into_inner().into())
 }
 
-fn json_decode<T: ReflectMessage + Default>(message: &[u8]) -> Result<T, TwirpError> {
-    let dynamic_message = dynamic_json_decode::<T>(message).map_err(|e| {
+fn json_decode<T: ReflectMessage + Default>(
+    message: &[u8],
+    registry: Option<&DescriptorRegistry>,
+    any_resolver: Option<&AnyResolver>,
+) -> Result<T, TwirpError> {
+    let descriptor = match registry {
+        Some(registry) => registry.descriptor::<T>(),
+        None => T::default().descriptor(),
+    };
+    let dynamic_message = match any_resolver {
+        Some(any_resolver) => any_resolver.decode_json(descriptor, message),
+        None => dynamic_json_decode(descriptor, message),
+    }
+    .map_err(|e| {
         TwirpError::wrap(
             TwirpErrorCode::Malformed,
             format!("Failed to parse JSON response: {e}"),
@@ -486,12 +1083,13 @@ fn json_decode<T: ReflectMessage + Default>(message: &[u8]) -> Result<T, TwirpEr
     })
 }
 
-fn dynamic_json_decode<T: ReflectMessage + Default>(
+fn dynamic_json_decode(
+    descriptor: MessageDescriptor,
     message: &[u8],
 ) -> Result<DynamicMessage, serde_json::Error> {
     let mut deserializer = serde_json::Deserializer::from_slice(message);
     let dynamic_message = DynamicMessage::deserialize_with_options(
-        T::default().descriptor(),
+        descriptor,
         &mut deserializer,
         // Ignore rather than returning an error when unknown fields are present following the proto3 spec:
         // https://protobuf.dev/programming-guides/proto3/#wire-safe-changes
@@ -602,6 +1200,127 @@ impl From<TwirpRequestBody> for reqwest_013::Body {
         body.0.into()
     }
 }
+/// A [`tower::Service`](Service) compatible with [`TwirpHttpClient`] built on top of [`hyper 1`](hyper_1) client.
+///
+/// It wraps a [`hyper_util::client::legacy::Client`] and can be configured with [`Hyper1Service::builder`].
+/// The connector is pluggable, [`HttpConnector`] is used by default.
+#[cfg(feature = "hyper-1")]
+#[derive(Clone)]
+pub struct Hyper1Service<C = HttpConnector>(
+    hyper_util::client::legacy::Client<C, TwirpRequestBody>,
+);
+
+#[cfg(feature = "hyper-1")]
+impl Hyper1Service {
+    /// Builds a new service with the default configuration and the plain HTTP [`HttpConnector`].
+    #[inline]
+    pub fn new() -> Self {
+        Self::builder().build_http()
+    }
+
+    /// Starts building a service with a custom configuration.
+    #[inline]
+    pub fn builder() -> Hyper1ServiceBuilder {
+        Hyper1ServiceBuilder::default()
+    }
+}
+
+#[cfg(feature = "hyper-1")]
+impl Default for Hyper1Service {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "hyper-1")]
+impl<C> From<hyper_util::client::legacy::Client<C, TwirpRequestBody>> for Hyper1Service<C> {
+    #[inline]
+    fn from(client: hyper_util::client::legacy::Client<C, TwirpRequestBody>) -> Self {
+        Self(client)
+    }
+}
+
+#[cfg(feature = "hyper-1")]
+impl<C: Connect + Clone + Send + Sync + 'static> Service<Request<TwirpRequestBody>>
+    for Hyper1Service<C>
+{
+    type Response = Response<hyper_1::body::Incoming>;
+    type Error = hyper_util::client::legacy::Error;
+    type Future = hyper_util::client::legacy::ResponseFuture;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Service::poll_ready(&mut self.0, cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: Request<TwirpRequestBody>) -> Self::Future {
+        Service::call(&mut self.0, req)
+    }
+}
+
+/// Builder for [`Hyper1Service`], returned by [`Hyper1Service::builder`].
+///
+/// Connections are driven by the Tokio runtime.
+#[cfg(feature = "hyper-1")]
+#[derive(Clone)]
+pub struct Hyper1ServiceBuilder(hyper_util::client::legacy::Builder);
+
+#[cfg(feature = "hyper-1")]
+impl Default for Hyper1ServiceBuilder {
+    #[inline]
+    fn default() -> Self {
+        Self(hyper_util::client::legacy::Builder::new(
+            TokioExecutor::new(),
+        ))
+    }
+}
+
+#[cfg(feature = "hyper-1")]
+impl Hyper1ServiceBuilder {
+    /// Only use HTTP/2 without HTTP/1 upgrade negotiation ("prior knowledge").
+    ///
+    /// This is required to use HTTP/2 over plain text connections.
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.0.http2_only(true);
+        self
+    }
+
+    /// Set a timeout for idle sockets being kept-alive in the connection pool.
+    ///
+    /// Pass [`None`] to disable the timeout. Default is 90 seconds.
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.0
+            .pool_timer(TokioTimer::new())
+            .pool_idle_timeout(timeout);
+        self
+    }
+
+    /// Set the maximum idle connection per host allowed in the connection pool.
+    ///
+    /// Default is [`usize::MAX`] (no limit).
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.0.pool_max_idle_per_host(max_idle);
+        self
+    }
+
+    /// Mutable access to the underlying [`hyper_util`] builder for settings not exposed here.
+    pub fn hyper_builder_mut(&mut self) -> &mut hyper_util::client::legacy::Builder {
+        &mut self.0
+    }
+
+    /// Builds the service with the plain HTTP [`HttpConnector`].
+    pub fn build_http(self) -> Hyper1Service {
+        self.build(HttpConnector::new())
+    }
+
+    /// Builds the service with a custom connector, for example to use TLS or another transport.
+    pub fn build<C: Connect + Clone>(self, connector: C) -> Hyper1Service<C> {
+        Hyper1Service(self.0.build(connector))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -610,7 +1329,10 @@ mod tests {
     use prost_reflect::prost_types::Timestamp;
     use std::future::Ready;
     use std::io;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::task::{Context, Poll};
+    use std::time::Duration;
     use tower::service_fn;
 
     const FILE_DESCRIPTOR_SET_BYTES: &[u8] = &[
@@ -693,6 +1415,39 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn typed_call_builder_encoding_override() -> Result<(), Box<dyn Error>> {
+        let service = service_fn(|request: Request<TwirpRequestBody>| async move {
+            assert_eq!(request.headers().get(CONTENT_TYPE), Some(&APPLICATION_JSON));
+            assert_eq!(
+                request.headers().get("x-foo"),
+                Some(&HeaderValue::from_static("bar"))
+            );
+            assert_eq!(Bytes::from(request.into_body()), "\"1970-01-01T00:00:10Z\"");
+            Ok::<_, TwirpError>(
+                Response::builder()
+                    .header(CONTENT_TYPE, APPLICATION_JSON)
+                    .body("\"1970-01-01T00:00:10Z\"".to_string())
+                    .unwrap(),
+            )
+        });
+
+        let client = TwirpHttpClient::new(service);
+        let request = Timestamp {
+            seconds: 10,
+            nanos: 0,
+        };
+        let response = client
+            .call_builder("/foo", &request)
+            .typed::<Timestamp>()
+            .header("x-foo", "bar")
+            .use_json()
+            .send()
+            .await?;
+        assert_eq!(response, request);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn call_builder_with_header_ok() -> Result<(), Box<dyn Error>> {
         let service = service_fn(|request: Request<TwirpRequestBody>| async move {
@@ -772,6 +1527,38 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn call_builder_send_with_metadata_ok() -> Result<(), Box<dyn Error>> {
+        let service = service_fn(|_: Request<TwirpRequestBody>| async move {
+            Ok::<_, TwirpError>(
+                Response::builder()
+                    .header(CONTENT_TYPE, APPLICATION_JSON)
+                    .header("x-ratelimit-remaining", "42")
+                    .body("\"1970-01-01T00:00:10Z\"".to_string())
+                    .unwrap(),
+            )
+        });
+
+        let client = TwirpHttpClient::new(service);
+        let response = client
+            .call_builder("/foo", &Timestamp::default())
+            .send_with_metadata::<Timestamp>()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("x-ratelimit-remaining"),
+            Some(&HeaderValue::from_static("42"))
+        );
+        assert_eq!(
+            response.into_message(),
+            Timestamp {
+                seconds: 10,
+                nanos: 0
+            }
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn call_builder_invalid_header_name_surfaces_on_send() -> Result<(), Box<dyn Error>> {
         let service = service_fn(|_: Request<TwirpRequestBody>| async move {
@@ -796,6 +1583,220 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "timeout")]
+    #[tokio::test]
+    async fn call_builder_timeout_exceeded() -> Result<(), Box<dyn Error>> {
+        let service = service_fn(|request: Request<TwirpRequestBody>| async move {
+            assert!((1..=10).contains(&advertised_timeout_ms(&request)));
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok::<Response<String>, TwirpError>(Response::new(String::new()))
+        });
+
+        let client = TwirpHttpClient::new(service);
+        let err = client
+            .call_builder("/foo", &Timestamp::default())
+            .timeout(Duration::from_millis(10))
+            .send::<Timestamp>()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            TwirpError::deadline_exceeded("The call did not complete within 10 ms")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_id_is_forwarded() -> Result<(), Box<dyn Error>> {
+        let service = service_fn(|request: Request<TwirpRequestBody>| async move {
+            assert_eq!(
+                request.headers().get(REQUEST_ID_HEADER),
+                Some(&HeaderValue::from_static("foo"))
+            );
+            Ok::<_, TwirpError>(
+                Response::builder()
+                    .header(CONTENT_TYPE, APPLICATION_JSON)
+                    .body("\"1970-01-01T00:00:10Z\"".to_string())
+                    .unwrap(),
+            )
+        });
+
+        let client = TwirpHttpClient::new(service);
+        request_id::scope(
+            "foo",
+            client.call::<_, Timestamp>("/foo", &Timestamp::default()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "timeout")]
+    #[tokio::test]
+    async fn client_default_timeout_is_advertised() -> Result<(), Box<dyn Error>> {
+        let service = service_fn(|request: Request<TwirpRequestBody>| async move {
+            assert!((4900..=5000).contains(&advertised_timeout_ms(&request)));
+            Ok::<_, TwirpError>(
+                Response::builder()
+                    .header(CONTENT_TYPE, APPLICATION_JSON)
+                    .body("\"1970-01-01T00:00:10Z\"".to_string())
+                    .unwrap(),
+            )
+        });
+
+        let mut client = TwirpHttpClient::new(service);
+        client.set_timeout(Duration::from_secs(5));
+        let response = client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await?;
+        assert_eq!(
+            response,
+            Timestamp {
+                seconds: 10,
+                nanos: 0
+            }
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "timeout")]
+    fn advertised_timeout_ms<B>(request: &Request<B>) -> u64 {
+        request.headers()[TIMEOUT_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[cfg(feature = "timeout")]
+    struct SlowReadyService;
+
+    #[cfg(feature = "timeout")]
+    impl TwirpHttpService for SlowReadyService {
+        type ResponseBody = String;
+        type Error = Infallible;
+
+        async fn ready(&self) -> Result<(), Infallible> {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(())
+        }
+
+        async fn call(
+            &self,
+            request: Request<TwirpRequestBody>,
+        ) -> Result<Response<String>, Infallible> {
+            let timeout_ms = advertised_timeout_ms(&request);
+            assert!(timeout_ms <= 800, "{timeout_ms} ms advertised");
+            Ok(Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON)
+                .body("\"1970-01-01T00:00:10Z\"".to_string())
+                .unwrap())
+        }
+    }
+
+    #[cfg(feature = "timeout")]
+    #[tokio::test]
+    async fn advertised_timeout_excludes_ready_time() -> Result<(), Box<dyn Error>> {
+        let mut client = TwirpHttpClient::new(SlowReadyService);
+        client.set_timeout(Duration::from_secs(1));
+        client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await?;
+        Ok(())
+    }
+
+    struct TokenInterceptor {
+        token: Mutex<&'static str>,
+    }
+
+    impl TwirpInterceptor for TokenInterceptor {
+        async fn intercept_request(
+            &self,
+            path: &str,
+            headers: &mut HeaderMap,
+        ) -> Result<(), TwirpError> {
+            assert_eq!(path, "/foo");
+            headers.insert(
+                http::header::AUTHORIZATION,
+                HeaderValue::from_static(*self.token.lock().unwrap()),
+            );
+            Ok(())
+        }
+
+        async fn intercept_error(&self, path: &str, error: &TwirpError) -> InterceptorAction {
+            assert_eq!(path, "/foo");
+            if error.code() != TwirpErrorCode::Unauthenticated {
+                return InterceptorAction::Return;
+            }
+            *self.token.lock().unwrap() = "refreshed";
+            InterceptorAction::Replay
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptor_replays_after_unauthenticated() -> Result<(), Box<dyn Error>> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let service_calls = calls.clone();
+        let service = service_fn(move |request: Request<TwirpRequestBody>| {
+            service_calls.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if request.headers().get(http::header::AUTHORIZATION)
+                    != Some(&HeaderValue::from_static("refreshed"))
+                {
+                    return Ok(TwirpError::unauthenticated("expired token").into());
+                }
+                Ok::<_, TwirpError>(
+                    Response::builder()
+                        .header(CONTENT_TYPE, APPLICATION_JSON)
+                        .body("\"1970-01-01T00:00:10Z\"".to_string())
+                        .unwrap(),
+                )
+            }
+        });
+
+        let mut client = TwirpHttpClient::new(service);
+        client.add_interceptor(TokenInterceptor {
+            token: Mutex::new("expired"),
+        });
+        let response = client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await?;
+        assert_eq!(
+            response,
+            Timestamp {
+                seconds: 10,
+                nanos: 0
+            }
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn interceptor_replays_only_once() -> Result<(), Box<dyn Error>> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let service_calls = calls.clone();
+        let service = service_fn(move |_: Request<TwirpRequestBody>| {
+            service_calls.fetch_add(1, Ordering::Relaxed);
+            async move {
+                Ok::<Response<String>, TwirpError>(
+                    TwirpError::unauthenticated("expired token").into(),
+                )
+            }
+        });
+
+        let mut client = TwirpHttpClient::new(service);
+        client.add_interceptor(TokenInterceptor {
+            token: Mutex::new("expired"),
+        });
+        let error = client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error, TwirpError::unauthenticated("expired token"));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn json_request_with_unknown_fields_ok() -> Result<(), Box<dyn Error>> {
         let service = service_fn(|request: Request<TwirpRequestBody>| async move {
@@ -896,6 +1897,73 @@ mod tests {
         Ok(())
     }
 
+    /// Serves on a local port a Twirp method `/foo` echoing the request and its HTTP version
+    #[cfg(feature = "hyper-1")]
+    async fn serve_echo() -> Result<String, Box<dyn Error>> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let router = axum::Router::new().route(
+            "/foo",
+            axum::routing::post(|version: http::Version, body: Bytes| async move {
+                (
+                    [
+                        (CONTENT_TYPE, APPLICATION_PROTOBUF),
+                        (
+                            HeaderName::from_static("x-http-version"),
+                            HeaderValue::from_str(&format!("{version:?}")).unwrap(),
+                        ),
+                    ],
+                    body,
+                )
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        Ok(format!("http://{address}"))
+    }
+
+    #[cfg(feature = "hyper-1")]
+    #[tokio::test]
+    async fn hyper_1_round_trip() -> Result<(), Box<dyn Error>> {
+        let client = TwirpHttpClient::new_using_hyper_1(serve_echo().await?);
+        let request = Timestamp {
+            seconds: 10,
+            nanos: 0,
+        };
+        let response = client
+            .call_builder("/foo", &request)
+            .send_with_metadata::<Timestamp>()
+            .await?;
+        assert_eq!(response.headers()["x-http-version"], "HTTP/1.1");
+        assert_eq!(response.into_message(), request);
+        Ok(())
+    }
+
+    #[cfg(feature = "hyper-1")]
+    #[tokio::test]
+    async fn hyper_1_builder_options() -> Result<(), Box<dyn Error>> {
+        let client = TwirpHttpClient::new_with_hyper_1_service(
+            Hyper1Service::builder()
+                .http2_prior_knowledge()
+                .pool_idle_timeout(Some(Duration::from_secs(1)))
+                .pool_max_idle_per_host(1)
+                .build_http(),
+            serve_echo().await?,
+        );
+        let request = Timestamp {
+            seconds: 10,
+            nanos: 0,
+        };
+        for _ in 0..2 {
+            let response = client
+                .call_builder("/foo", &request)
+                .send_with_metadata::<Timestamp>()
+                .await?;
+            assert_eq!(response.headers()["x-http-version"], "HTTP/2.0");
+            assert_eq!(response.into_message(), request);
+        }
+        Ok(())
+    }
+
     #[tokio::test]
     async fn request_with_base_twirp_error() -> Result<(), Box<dyn Error>> {
         let service = service_fn(|request: Request<TwirpRequestBody>| async move {
@@ -1074,6 +2142,101 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn circuit_breaker_opens_per_method() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_in_service = calls.clone();
+        let service = service_fn(move |request: Request<TwirpRequestBody>| {
+            calls_in_service.fetch_add(1, Ordering::Relaxed);
+            async move {
+                Ok::<Response<String>, Infallible>(if request.uri() == "/broken" {
+                    TwirpError::unavailable("down").into()
+                } else {
+                    TwirpError::not_found("not found").into()
+                })
+            }
+        });
+
+        let mut client = TwirpHttpClient::new(service);
+        client.set_circuit_breaker(
+            CircuitBreaker::new()
+                .window_size(4)
+                .minimum_calls(2)
+                .open_duration(Duration::from_secs(60)),
+        );
+        for _ in 0..2 {
+            let error = client
+                .call::<_, Timestamp>("/broken", &Timestamp::default())
+                .await
+                .unwrap_err();
+            assert_eq!(error.meta("circuit_open"), None);
+        }
+        let error = client
+            .call::<_, Timestamp>("/broken", &Timestamp::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), TwirpErrorCode::Unavailable);
+        assert_eq!(error.meta("circuit_open"), Some("true"));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        // NotFound is not a failure and other methods are not impacted
+        for _ in 0..3 {
+            let error = client
+                .call::<_, Timestamp>("/other", &Timestamp::default())
+                .await
+                .unwrap_err();
+            assert_eq!(error.code(), TwirpErrorCode::NotFound);
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_half_open() {
+        let healthy = Arc::new(Mutex::new(false));
+        let healthy_in_service = healthy.clone();
+        let service = service_fn(move |_: Request<TwirpRequestBody>| {
+            let healthy = *healthy_in_service.lock().unwrap();
+            async move {
+                Ok::<_, Infallible>(if healthy {
+                    Response::builder()
+                        .header(CONTENT_TYPE, APPLICATION_JSON)
+                        .body("\"1970-01-01T00:00:10Z\"".to_string())
+                        .unwrap()
+                } else {
+                    TwirpError::unavailable("down").into()
+                })
+            }
+        });
+
+        let mut client = TwirpHttpClient::new(service);
+        client.set_circuit_breaker(
+            CircuitBreaker::new()
+                .minimum_calls(1)
+                .open_duration(Duration::ZERO),
+        );
+        // Opens the circuit
+        client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await
+            .unwrap_err();
+        // Failing probe: the circuit opens again
+        let error = client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.meta("circuit_open"), None);
+        // Successful probe: the circuit closes
+        *healthy.lock().unwrap() = true;
+        client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await
+            .unwrap();
+        client
+            .call::<_, Timestamp>("/foo", &Timestamp::default())
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn response_future_is_send() {
         fn is_send<T: Send>(_: T) {}
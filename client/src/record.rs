@@ -0,0 +1,580 @@
+//! Record and replay transports for deterministic client tests.
+//!
+//! [`RecordingService`] wraps a real [`TwirpHttpService`] and writes the request/response pairs it sees to a JSON fixture file.
+//! [`ReplayService`] loads this fixture file and serves the recorded responses back without any networking.
+//!
+//! Messages are stored using the protobuf JSON mapping so fixtures are readable and can be edited by hand.
+//! Requests are compared semantically: the field order or the encoding (JSON or binary protobuf) does not matter.
+//! If no recorded request matches, the call fails with a diff against the closest recorded request.
+//!
+//! Both need a [`DescriptorPool`] containing the called services, e.g. `ExampleRequest::default().descriptor().parent_pool().clone()`.
+//!
+//! ```no_run
+//! use prost_reflect::ReflectMessage;
+//! use prost_reflect::prost_types::Timestamp;
+//! use twurst_client::TwirpHttpClient;
+//! use twurst_client::record::ReplayService;
+//!
+//! let pool = Timestamp::default().descriptor().parent_pool().clone();
+//! let _client = TwirpHttpClient::new(ReplayService::from_file(pool, "tests/fixtures/example.json")?);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::{APPLICATION_JSON, APPLICATION_PROTOBUF, TwirpHttpService, TwirpRequestBody};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::{HeaderValue, Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use prost_reflect::bytes::Bytes;
+use prost_reflect::{DescriptorPool, DeserializeOptions, DynamicMessage, MessageDescriptor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// [`TwirpHttpService`] recording the requests sent to the wrapped service and their responses to a JSON fixture file.
+///
+/// The fixture file is overwritten and is updated after each call.
+/// It is written using [`tokio::fs`] so the calls must be done within a Tokio runtime.
+pub struct RecordingService<S> {
+    service: S,
+    pool: DescriptorPool,
+    path: PathBuf,
+    fixture: Mutex<Fixture>,
+    // number of interactions in the fixture file, to not overwrite it with an older version
+    written_interactions: tokio::sync::Mutex<usize>,
+}
+
+impl<S: TwirpHttpService + Sync> RecordingService<S> {
+    /// Wraps `service` and records its calls to the fixture file at `path`.
+    ///
+    /// `pool` must contain the called services.
+    pub fn new(service: S, pool: DescriptorPool, path: impl Into<PathBuf>) -> Self {
+        Self {
+            service,
+            pool,
+            path: path.into(),
+            fixture: Mutex::new(Fixture::default()),
+            written_interactions: tokio::sync::Mutex::new(0),
+        }
+    }
+
+    /// Writes the serialized fixture unless a more recent version has already been written
+    async fn save(&self, interactions: usize, content: String) -> Result<(), RecordReplayError> {
+        let mut written_interactions = self.written_interactions.lock().await;
+        if *written_interactions >= interactions {
+            return Ok(());
+        }
+        tokio::fs::write(&self.path, content).await.map_err(|e| {
+            RecordReplayError::wrap(&format!("Failed to write {}", self.path.display()), e)
+        })?;
+        *written_interactions = interactions;
+        Ok(())
+    }
+}
+
+impl<S: TwirpHttpService<ResponseBody: Send + Body<Data: Send>> + Sync> TwirpHttpService
+    for RecordingService<S>
+{
+    type ResponseBody = Full<Bytes>;
+    type Error = RecordReplayError;
+
+    async fn ready(&self) -> Result<(), Self::Error> {
+        self.service
+            .ready()
+            .await
+            .map_err(|e| RecordReplayError::wrap("The recorded service is not ready", e))
+    }
+
+    async fn call(
+        &self,
+        request: Request<TwirpRequestBody>,
+    ) -> Result<Response<Self::ResponseBody>, Self::Error> {
+        let (parts, body) = request.into_parts();
+        let body = Bytes::from(body);
+        let method = Method::from_uri_path(&self.pool, parts.uri.path())?;
+        let request_json = decode_message(method.input, parts.headers.get(CONTENT_TYPE), &body)?;
+
+        let response = self
+            .service
+            .call(Request::from_parts(parts, body.into()))
+            .await
+            .map_err(|e| RecordReplayError::wrap("Transport error", e))?;
+        let (parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .map_err(|e| RecordReplayError::wrap("Failed to load the response body", e))?
+            .to_bytes();
+
+        let (response_body, content_type) = if parts.status == StatusCode::OK {
+            (
+                decode_message(method.output, parts.headers.get(CONTENT_TYPE), &body)?,
+                None,
+            )
+        } else {
+            (
+                serde_json::from_slice(&body)
+                    .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into())),
+                parts
+                    .headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| Some(value.to_str().ok()?.into())),
+            )
+        };
+        let headers = parts
+            .headers
+            .iter()
+            .filter(|(name, _)| **name != CONTENT_TYPE && **name != CONTENT_LENGTH)
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+            .collect();
+        let (interactions, content) = {
+            let mut fixture = self.fixture.lock().unwrap();
+            fixture.interactions.push(Interaction {
+                path: method.path,
+                request: request_json,
+                response: RecordedResponse {
+                    status: parts.status.as_u16(),
+                    content_type,
+                    headers,
+                    body: response_body,
+                },
+            });
+            (fixture.interactions.len(), fixture.to_json()?)
+        };
+        self.save(interactions, content).await?;
+        Ok(Response::from_parts(parts, Full::new(body)))
+    }
+}
+
+/// [`TwirpHttpService`] serving responses recorded by a [`RecordingService`].
+///
+/// Each recorded interaction is served once in the recording order.
+/// When all the interactions matching a request have been served, the last one is served again.
+pub struct ReplayService {
+    pool: DescriptorPool,
+    interactions: Vec<Interaction>,
+    served: Mutex<Vec<bool>>,
+}
+
+impl ReplayService {
+    /// Loads a fixture file written by [`RecordingService`]
+    pub fn from_file(pool: DescriptorPool, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let fixture = fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read fixture {}: {e}", path.display()),
+            )
+        })?;
+        Self::from_json(pool, &fixture)
+    }
+
+    /// Loads the content of a fixture file written by [`RecordingService`]
+    pub fn from_json(pool: DescriptorPool, fixture: &[u8]) -> io::Result<Self> {
+        let fixture = serde_json::from_slice::<Fixture>(fixture)?;
+        Ok(Self {
+            pool,
+            served: Mutex::new(vec![false; fixture.interactions.len()]),
+            interactions: fixture.interactions,
+        })
+    }
+
+    fn find_interaction(
+        &self,
+        path: &str,
+        request: &Value,
+    ) -> Result<&Interaction, RecordReplayError> {
+        let mut served = self.served.lock().unwrap();
+        let mut last_match = None;
+        for (i, interaction) in self.interactions.iter().enumerate() {
+            if interaction.path == path && interaction.request == *request {
+                if !served[i] {
+                    served[i] = true;
+                    return Ok(interaction);
+                }
+                last_match = Some(interaction);
+            }
+        }
+        if let Some(interaction) = last_match {
+            return Ok(interaction);
+        }
+
+        // We build a nice error message with the closest recorded request
+        let actual = to_pretty_json(request);
+        let closest = self
+            .interactions
+            .iter()
+            .filter(|interaction| interaction.path == path)
+            .map(|interaction| line_diff(&to_pretty_json(&interaction.request), &actual))
+            .min_by_key(|(_, changes)| *changes);
+        Err(RecordReplayError::new(match closest {
+            Some((diff, _)) => format!(
+                "No recorded request to {path} matches, diff with the closest recorded request (- recorded, + actual):\n{diff}"
+            ),
+            None => format!("No recorded request to {path}"),
+        }))
+    }
+}
+
+impl TwirpHttpService for ReplayService {
+    type ResponseBody = Full<Bytes>;
+    type Error = RecordReplayError;
+
+    async fn ready(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn call(
+        &self,
+        request: Request<TwirpRequestBody>,
+    ) -> Result<Response<Self::ResponseBody>, Self::Error> {
+        let (parts, body) = request.into_parts();
+        let method = Method::from_uri_path(&self.pool, parts.uri.path())?;
+        let request_json = decode_message(
+            method.input,
+            parts.headers.get(CONTENT_TYPE),
+            &Bytes::from(body),
+        )?;
+        let recorded = &self.find_interaction(&method.path, &request_json)?.response;
+
+        let mut response = Response::builder().status(recorded.status);
+        // Successful responses are stored using the JSON mapping
+        if recorded.status == StatusCode::OK.as_u16() {
+            response = response.header(CONTENT_TYPE, APPLICATION_JSON);
+        } else if let Some(content_type) = &recorded.content_type {
+            response = response.header(CONTENT_TYPE, content_type.as_str());
+        }
+        for (name, value) in &recorded.headers {
+            response = response.header(name.as_str(), value.as_str());
+        }
+        let body = match &recorded.body {
+            Value::String(body) if recorded.status != StatusCode::OK.as_u16() => body.clone(),
+            body => body.to_string(),
+        };
+        response
+            .body(Full::new(body.into()))
+            .map_err(|e| RecordReplayError::wrap("Invalid recorded response", e))
+    }
+}
+
+/// Error returned by [`RecordingService`] and [`ReplayService`].
+#[derive(Debug)]
+pub struct RecordReplayError {
+    message: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl RecordReplayError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    fn wrap(context: &str, error: impl Error + Send + Sync + 'static) -> Self {
+        Self {
+            message: format!("{context}: {error}"),
+            source: Some(Box::new(error)),
+        }
+    }
+}
+
+impl fmt::Display for RecordReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for RecordReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref()?.as_ref())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Fixture {
+    interactions: Vec<Interaction>,
+}
+
+impl Fixture {
+    fn to_json(&self) -> Result<String, RecordReplayError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| RecordReplayError::wrap("Failed to serialize the fixture", e))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Interaction {
+    path: String,
+    request: Value,
+    response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    /// Content type of the error responses, successful responses are always replayed as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    body: Value,
+}
+
+/// The Twirp method targeted by a request
+struct Method {
+    /// The `/package.Service/Method` path
+    path: String,
+    input: MessageDescriptor,
+    output: MessageDescriptor,
+}
+
+impl Method {
+    fn from_uri_path(pool: &DescriptorPool, uri_path: &str) -> Result<Self, RecordReplayError> {
+        let mut segments = uri_path.rsplit('/');
+        let method_name = segments.next().unwrap_or_default();
+        let service_name = segments.next().unwrap_or_default();
+        let method = pool
+            .get_service_by_name(service_name)
+            .and_then(|service| service.methods().find(|m| m.name() == method_name))
+            .ok_or_else(|| {
+                RecordReplayError::new(format!(
+                    "The method of {uri_path} is not in the descriptor pool"
+                ))
+            })?;
+        Ok(Self {
+            path: format!("/{service_name}/{method_name}"),
+            input: method.input(),
+            output: method.output(),
+        })
+    }
+}
+
+/// Decodes a message and returns its canonical JSON mapping
+fn decode_message(
+    descriptor: MessageDescriptor,
+    content_type: Option<&HeaderValue>,
+    body: &Bytes,
+) -> Result<Value, RecordReplayError> {
+    let message = if content_type == Some(&APPLICATION_PROTOBUF) {
+        DynamicMessage::decode(descriptor, body.clone()).map_err(|e| {
+            RecordReplayError::wrap("Failed to decode the binary protobuf message", e)
+        })?
+    } else {
+        let mut deserializer = serde_json::Deserializer::from_slice(body);
+        DynamicMessage::deserialize_with_options(
+            descriptor,
+            &mut deserializer,
+            &DeserializeOptions::new().deny_unknown_fields(false),
+        )
+        .map_err(|e| RecordReplayError::wrap("Failed to decode the JSON message", e))?
+    };
+    serde_json::to_value(&message)
+        .map_err(|e| RecordReplayError::wrap("Failed to encode the message to JSON", e))
+}
+
+fn to_pretty_json(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+/// Line based diff using the longest common subsequence, returns the diff and the number of changed lines
+fn line_diff(expected: &str, actual: &str) -> (String, usize) {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    let mut lcs = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut diff = String::new();
+    let mut changes = 0;
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff.push_str(&format!("  {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("- {}\n", expected[i]));
+            changes += 1;
+            i += 1;
+        } else {
+            diff.push_str(&format!("+ {}\n", actual[j]));
+            changes += 1;
+            j += 1;
+        }
+    }
+    (diff, changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TwirpError, TwirpErrorCode, TwirpHttpClient};
+    use prost_reflect::ReflectMessage;
+    use prost_reflect::prost::Message;
+    use prost_reflect::prost_types::{
+        FileDescriptorProto, MethodDescriptorProto, ServiceDescriptorProto, Timestamp,
+    };
+    use std::convert::Infallible;
+    use tower::service_fn;
+
+    const ECHO: &str = "/test.TestService/Echo";
+
+    fn pool() -> DescriptorPool {
+        let mut pool = Timestamp::default().descriptor().parent_pool().clone();
+        pool.add_file_descriptor_proto(FileDescriptorProto {
+            name: Some("test.proto".into()),
+            package: Some("test".into()),
+            dependency: vec!["google/protobuf/timestamp.proto".into()],
+            service: vec![ServiceDescriptorProto {
+                name: Some("TestService".into()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Echo".into()),
+                    input_type: Some(".google.protobuf.Timestamp".into()),
+                    output_type: Some(".google.protobuf.Timestamp".into()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            syntax: Some("proto3".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fixture.json");
+        let recording = TwirpHttpClient::new_with_base(
+            RecordingService::new(
+                service_fn(|request: Request<TwirpRequestBody>| async move {
+                    let request = Timestamp::decode(Bytes::from(request.into_body())).unwrap();
+                    if request.seconds < 0 {
+                        return Ok(TwirpError::invalid_argument("negative").into());
+                    }
+                    if request.seconds == 0 {
+                        return Ok(Response::builder()
+                            .status(StatusCode::BAD_GATEWAY)
+                            .header(CONTENT_TYPE, "text/plain")
+                            .body(Full::new(Bytes::from("upstream is down")))
+                            .unwrap());
+                    }
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .header(CONTENT_TYPE, APPLICATION_PROTOBUF)
+                            .header("x-request-id", "foo")
+                            .body(Full::new(Bytes::from(request.encode_to_vec())))
+                            .unwrap(),
+                    )
+                }),
+                pool(),
+                &path,
+            ),
+            "http://example.com/twirp",
+        );
+        let request = Timestamp {
+            seconds: 10,
+            nanos: 1,
+        };
+        assert_eq!(
+            recording.call::<_, Timestamp>(ECHO, &request).await?,
+            request
+        );
+        recording
+            .call::<_, Timestamp>(
+                ECHO,
+                &Timestamp {
+                    seconds: -1,
+                    nanos: 0,
+                },
+            )
+            .await
+            .unwrap_err();
+        recording
+            .call::<_, Timestamp>(ECHO, &Timestamp::default())
+            .await
+            .unwrap_err();
+
+        // We replay using JSON to check that the comparison is semantic
+        let mut replay = TwirpHttpClient::new(ReplayService::from_file(pool(), &path)?);
+        replay.use_json();
+        let response = replay
+            .call_builder(ECHO, &request)
+            .send_with_metadata::<Timestamp>()
+            .await?;
+        assert_eq!(response.headers().get("x-request-id").unwrap(), "foo");
+        assert_eq!(response.into_message(), request);
+        let error = replay
+            .call::<_, Timestamp>(
+                ECHO,
+                &Timestamp {
+                    seconds: -1,
+                    nanos: 0,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), TwirpErrorCode::InvalidArgument);
+        assert_eq!(error.message(), "negative");
+
+        // The content type of non-JSON error bodies is kept
+        let response = ReplayService::from_file(pool(), &path)?
+            .call(
+                Request::post(ECHO)
+                    .header(CONTENT_TYPE, APPLICATION_JSON)
+                    .body(TwirpRequestBody::from(Bytes::from(
+                        "\"1970-01-01T00:00:00Z\"",
+                    )))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/plain");
+        assert_eq!(
+            response.into_body().collect().await?.to_bytes(),
+            "upstream is down"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_mismatch_diff() -> Result<(), Box<dyn Error>> {
+        let replay = TwirpHttpClient::new(ReplayService::from_json(
+            pool(),
+            br#"{"interactions": [{
+                "path": "/test.TestService/Echo",
+                "request": "1970-01-01T00:00:10Z",
+                "response": {"status": 200, "body": "1970-01-01T00:00:10Z"}
+            }]}"#,
+        )?);
+        let error = replay
+            .call::<_, Timestamp>(
+                ECHO,
+                &Timestamp {
+                    seconds: 11,
+                    nanos: 0,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Transport error during the request: No recorded request to /test.TestService/Echo matches, diff with the closest recorded request (- recorded, + actual):\n- \"1970-01-01T00:00:10Z\"\n+ \"1970-01-01T00:00:11Z\"\n"
+        );
+        Ok(())
+    }
+}
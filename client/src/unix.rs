@@ -0,0 +1,77 @@
+use crate::{Hyper1Service, Hyper1ServiceBuilder, TwirpHttpClient};
+use http::Uri;
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::UnixStream;
+use tower_service::Service;
+
+/// [`hyper_util`] connector opening connections to a Unix domain socket, whatever the request URL is.
+///
+/// To be used with [`Hyper1ServiceBuilder::build_unix`] or [`Hyper1ServiceBuilder::build`].
+#[derive(Clone, Debug)]
+pub struct UnixConnector {
+    path: Arc<Path>,
+}
+
+impl UnixConnector {
+    /// Connector to the socket at `path`, which is only opened when a connection is needed
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into().into(),
+        }
+    }
+
+    /// Path of the socket
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Service<Uri> for UnixConnector {
+    type Response = TokioIo<UnixStream>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<TokioIo<UnixStream>>> + Send>>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+        Box::pin(async move { Ok(TokioIo::new(UnixStream::connect(path).await?)) })
+    }
+}
+
+impl Hyper1ServiceBuilder {
+    /// Builds the service with a [`UnixConnector`] sending all requests to the Unix domain socket at `path`.
+    ///
+    /// HTTP/1.1 is used by default, HTTP/2 with [`http2_prior_knowledge`](Self::http2_prior_knowledge).
+    pub fn build_unix(self, path: impl Into<PathBuf>) -> Hyper1Service<UnixConnector> {
+        self.build(UnixConnector::new(path))
+    }
+}
+
+impl TwirpHttpClient<Hyper1Service<UnixConnector>> {
+    /// Builds a new client sending the requests over HTTP/1.1 to the Unix domain socket at `socket_path`.
+    ///
+    /// `base_url` must still be absolute, its host is only used in the `Host` header,
+    /// and its path is the prefix of the Twirp routes.
+    ///
+    /// ```
+    /// use twurst_client::TwirpHttpClient;
+    ///
+    /// let _client = TwirpHttpClient::new_using_unix_socket("/run/daemon.sock", "http://localhost/twirp");
+    /// ```
+    pub fn new_using_unix_socket(
+        socket_path: impl Into<PathBuf>,
+        base_url: impl Into<String>,
+    ) -> Self {
+        Self::new_with_hyper_1_service(Hyper1Service::builder().build_unix(socket_path), base_url)
+    }
+}
@@ -0,0 +1,600 @@
+use crate::registry::DescriptorRegistry;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use prost_reflect::prost::Message;
+use prost_reflect::{
+    DescriptorPool, DeserializeOptions, DynamicMessage, FieldDescriptor, Kind, MapKey,
+    MessageDescriptor, ReflectMessage, Value,
+};
+use serde::de::Error as _;
+use serde_json::Map;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+const ANY: &str = "google.protobuf.Any";
+
+/// Message types with a special JSON encoding, wrapped in a `value` key inside `Any`
+const WELL_KNOWN_TYPES: [&str; 16] = [
+    "google.protobuf.Any",
+    "google.protobuf.BoolValue",
+    "google.protobuf.BytesValue",
+    "google.protobuf.DoubleValue",
+    "google.protobuf.Duration",
+    "google.protobuf.FieldMask",
+    "google.protobuf.FloatValue",
+    "google.protobuf.Int32Value",
+    "google.protobuf.Int64Value",
+    "google.protobuf.ListValue",
+    "google.protobuf.StringValue",
+    "google.protobuf.Struct",
+    "google.protobuf.Timestamp",
+    "google.protobuf.UInt32Value",
+    "google.protobuf.UInt64Value",
+    "google.protobuf.Value",
+];
+
+/// Maps the type URLs of `google.protobuf.Any` payloads to message descriptors.
+///
+/// Implemented for [`DescriptorPool`], [`DescriptorRegistry`] (both looking up the type name after the last `/`)
+/// and for closures.
+pub trait TypeUrlResolver: Send + Sync {
+    fn resolve(&self, type_url: &str) -> Option<MessageDescriptor>;
+}
+
+impl TypeUrlResolver for DescriptorPool {
+    fn resolve(&self, type_url: &str) -> Option<MessageDescriptor> {
+        self.get_message_by_name(type_name(type_url))
+    }
+}
+
+impl TypeUrlResolver for DescriptorRegistry {
+    fn resolve(&self, type_url: &str) -> Option<MessageDescriptor> {
+        self.pool().resolve(type_url)
+    }
+}
+
+impl<F: Fn(&str) -> Option<MessageDescriptor> + Send + Sync> TypeUrlResolver for F {
+    fn resolve(&self, type_url: &str) -> Option<MessageDescriptor> {
+        self(type_url)
+    }
+}
+
+/// What to do with `google.protobuf.Any` payloads whose type URL is not resolved
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownAny {
+    /// Fail the JSON encoding or decoding (the default)
+    #[default]
+    Error,
+    /// Drop the `Any` value
+    Skip,
+    /// Keep the payload as is, encoded in JSON as `{"@type": "<type URL>", "value": "<base64 payload>"}`
+    Base64,
+}
+
+/// Runtime resolution of the `google.protobuf.Any` types in the JSON encoding.
+///
+/// By default, `Any` types are looked up by name in the descriptor pool of the message being encoded
+/// and the JSON encoding fails if the type is unknown.
+/// An `AnyResolver` first asks its [`TypeUrlResolver`], then falls back to this pool,
+/// and the [`UnknownAny`] behavior is applied if the type is still not found.
+/// Set it with `TwirpHttpClient::set_any_resolver` in `twurst-client`
+/// or add it to the request extensions, e.g. with an `axum::Extension` layer, in `twurst-server`.
+///
+/// ```
+/// use prost_reflect::DescriptorPool;
+/// use twurst_error::any::{AnyResolver, UnknownAny};
+///
+/// // Relays the payloads of unknown types as base64
+/// let _resolver = AnyResolver::new(DescriptorPool::global()).unknown_any(UnknownAny::Base64);
+/// ```
+#[derive(Clone)]
+pub struct AnyResolver {
+    resolver: Arc<dyn TypeUrlResolver>,
+    unknown_any: UnknownAny,
+    options: DeserializeOptions,
+}
+
+impl AnyResolver {
+    /// Resolves the `Any` types with `resolver` first, then with the descriptor pool of the encoded message.
+    ///
+    /// Types found by neither are an error, see [`unknown_any`](Self::unknown_any) to relay or drop them instead.
+    pub fn new(resolver: impl TypeUrlResolver + 'static) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            unknown_any: UnknownAny::default(),
+            // Ignore rather than returning an error when unknown fields are present following the proto3 spec:
+            // https://protobuf.dev/programming-guides/proto3/#wire-safe-changes
+            options: DeserializeOptions::new().deny_unknown_fields(false),
+        }
+    }
+
+    /// Set what to do with the `Any` payloads whose type is not resolved
+    pub fn unknown_any(mut self, unknown_any: UnknownAny) -> Self {
+        self.unknown_any = unknown_any;
+        self
+    }
+
+    /// Encodes the message in JSON, resolving the `Any` types
+    pub fn encode_json(
+        &self,
+        message: &DynamicMessage,
+    ) -> Result<serde_json::Value, serde_json::Error> {
+        Ok(self
+            .serialize_message(message)?
+            .unwrap_or_else(|| serde_json::Value::Object(Map::new())))
+    }
+
+    /// Decodes a JSON message of the given type, resolving the `Any` types
+    pub fn decode_json(
+        &self,
+        descriptor: MessageDescriptor,
+        json: &[u8],
+    ) -> Result<DynamicMessage, serde_json::Error> {
+        let json = serde_json::from_slice(json)?;
+        Ok(self
+            .deserialize_message(descriptor.clone(), json)?
+            .unwrap_or_else(|| DynamicMessage::new(descriptor)))
+    }
+
+    /// Returns `None` if the message is a skipped `Any`
+    fn serialize_message(
+        &self,
+        message: &DynamicMessage,
+    ) -> Result<Option<serde_json::Value>, serde_json::Error> {
+        let descriptor = message.descriptor();
+        if descriptor.full_name() == ANY {
+            return self.serialize_any(message);
+        }
+        let fields = descriptor
+            .fields()
+            .filter(|field| message.has_field(field) && reaches_any(field))
+            .collect::<Vec<_>>();
+        if fields.is_empty() {
+            return serde_json::to_value(message).map(Some);
+        }
+        // The fields that might contain an Any are serialized by hand, the other ones by prost-reflect
+        let mut rest = message.clone();
+        for field in &fields {
+            rest.clear_field(field);
+        }
+        let serde_json::Value::Object(mut object) = serde_json::to_value(&rest)? else {
+            return Err(serde_json::Error::custom(
+                "messages must serialize to objects",
+            ));
+        };
+        for field in fields {
+            let json = match &*message.get_field(&field) {
+                Value::Message(value) => match self.serialize_message(value)? {
+                    Some(json) => json,
+                    None => continue,
+                },
+                Value::List(values) => {
+                    let mut array = Vec::with_capacity(values.len());
+                    for value in values {
+                        if let Some(json) = self.serialize_value(value)? {
+                            array.push(json);
+                        }
+                    }
+                    serde_json::Value::Array(array)
+                }
+                Value::Map(values) => {
+                    let mut map = Map::new();
+                    for (key, value) in values {
+                        if let Some(json) = self.serialize_value(value)? {
+                            map.insert(map_key_to_string(key), json);
+                        }
+                    }
+                    serde_json::Value::Object(map)
+                }
+                _ => return Err(serde_json::Error::custom("unexpected non-message value")),
+            };
+            object.insert(field.json_name().into(), json);
+        }
+        Ok(Some(serde_json::Value::Object(object)))
+    }
+
+    fn serialize_value(
+        &self,
+        value: &Value,
+    ) -> Result<Option<serde_json::Value>, serde_json::Error> {
+        match value {
+            Value::Message(value) => self.serialize_message(value),
+            _ => Err(serde_json::Error::custom("unexpected non-message value")),
+        }
+    }
+
+    fn serialize_any(
+        &self,
+        any: &DynamicMessage,
+    ) -> Result<Option<serde_json::Value>, serde_json::Error> {
+        let (type_url, value) = any_fields(any);
+        let mut object = Map::new();
+        object.insert("@type".into(), type_url.clone().into());
+        match self.resolve(&type_url, any.descriptor().parent_pool()) {
+            Some(descriptor) => {
+                let inner =
+                    DynamicMessage::decode(descriptor.clone(), value.as_slice()).map_err(|e| {
+                        serde_json::Error::custom(format!(
+                            "invalid payload for Any of type {type_url}: {e}"
+                        ))
+                    })?;
+                let json = self
+                    .serialize_message(&inner)?
+                    .unwrap_or_else(|| serde_json::Value::Object(Map::new()));
+                match json {
+                    serde_json::Value::Object(fields)
+                        if !WELL_KNOWN_TYPES.contains(&descriptor.full_name()) =>
+                    {
+                        object.extend(fields)
+                    }
+                    json => {
+                        object.insert("value".into(), json);
+                    }
+                }
+            }
+            None => match self.unknown_any {
+                UnknownAny::Error => {
+                    return Err(serde_json::Error::custom(format!(
+                        "unknown type URL in Any: {type_url}"
+                    )));
+                }
+                UnknownAny::Skip => return Ok(None),
+                UnknownAny::Base64 => {
+                    object.insert("value".into(), STANDARD.encode(value).into());
+                }
+            },
+        }
+        Ok(Some(serde_json::Value::Object(object)))
+    }
+
+    /// Returns `None` if the message is a skipped `Any`
+    fn deserialize_message(
+        &self,
+        descriptor: MessageDescriptor,
+        json: serde_json::Value,
+    ) -> Result<Option<DynamicMessage>, serde_json::Error> {
+        if descriptor.full_name() == ANY {
+            return self.deserialize_any(descriptor, json);
+        }
+        let fields = descriptor.fields().filter(reaches_any).collect::<Vec<_>>();
+        let serde_json::Value::Object(mut object) = json else {
+            return DynamicMessage::deserialize_with_options(descriptor, json, &self.options)
+                .map(Some);
+        };
+        // The fields that might contain an Any are deserialized by hand, the other ones by prost-reflect
+        let mut values = Vec::new();
+        for field in fields {
+            let json_name_value = object.remove(field.json_name());
+            if let Some(json) = object.remove(field.name()).or(json_name_value) {
+                values.push((field, json));
+            }
+        }
+        let mut message = DynamicMessage::deserialize_with_options(
+            descriptor,
+            serde_json::Value::Object(object),
+            &self.options,
+        )?;
+        for (field, json) in values {
+            if let Some(value) = self.deserialize_field(&field, json)? {
+                message.set_field(&field, value);
+            }
+        }
+        Ok(Some(message))
+    }
+
+    fn deserialize_field(
+        &self,
+        field: &FieldDescriptor,
+        json: serde_json::Value,
+    ) -> Result<Option<Value>, serde_json::Error> {
+        let Kind::Message(descriptor) = field.kind() else {
+            return Err(serde_json::Error::custom("unexpected non-message field"));
+        };
+        Ok(match json {
+            serde_json::Value::Null => None,
+            serde_json::Value::Array(array) if field.is_list() => {
+                let mut values = Vec::with_capacity(array.len());
+                for json in array {
+                    if let Some(value) = self.deserialize_message(descriptor.clone(), json)? {
+                        values.push(Value::Message(value));
+                    }
+                }
+                Some(Value::List(values))
+            }
+            serde_json::Value::Object(object) if field.is_map() => {
+                let key_kind = descriptor.map_entry_key_field().kind();
+                let Kind::Message(value_descriptor) = descriptor.map_entry_value_field().kind()
+                else {
+                    return Err(serde_json::Error::custom(
+                        "unexpected non-message map value",
+                    ));
+                };
+                let mut values = HashMap::with_capacity(object.len());
+                for (key, json) in object {
+                    if let Some(value) = self.deserialize_message(value_descriptor.clone(), json)? {
+                        values.insert(map_key_from_string(&key_kind, &key)?, Value::Message(value));
+                    }
+                }
+                Some(Value::Map(values))
+            }
+            _ if field.is_list() || field.is_map() => {
+                return Err(serde_json::Error::custom(format!(
+                    "invalid JSON value for field {}",
+                    field.name()
+                )));
+            }
+            json => self
+                .deserialize_message(descriptor, json)?
+                .map(Value::Message),
+        })
+    }
+
+    fn deserialize_any(
+        &self,
+        descriptor: MessageDescriptor,
+        json: serde_json::Value,
+    ) -> Result<Option<DynamicMessage>, serde_json::Error> {
+        let serde_json::Value::Object(mut object) = json else {
+            return Err(serde_json::Error::custom("Any must be a JSON object"));
+        };
+        let Some(serde_json::Value::String(type_url)) = object.remove("@type") else {
+            return Err(serde_json::Error::custom("Any must have a @type string"));
+        };
+        let value = match self.resolve(&type_url, descriptor.parent_pool()) {
+            Some(inner_descriptor) => {
+                let json = if WELL_KNOWN_TYPES.contains(&inner_descriptor.full_name()) {
+                    object.remove("value").unwrap_or_default()
+                } else {
+                    serde_json::Value::Object(object)
+                };
+                self.deserialize_message(inner_descriptor.clone(), json)?
+                    .unwrap_or_else(|| DynamicMessage::new(inner_descriptor))
+                    .encode_to_vec()
+            }
+            None => match self.unknown_any {
+                UnknownAny::Error => {
+                    return Err(serde_json::Error::custom(format!(
+                        "unknown type URL in Any: {type_url}"
+                    )));
+                }
+                UnknownAny::Skip => return Ok(None),
+                UnknownAny::Base64 => {
+                    let Some(serde_json::Value::String(value)) = object.remove("value") else {
+                        return Err(serde_json::Error::custom(format!(
+                            "Any of unknown type {type_url} must have a base64 value string"
+                        )));
+                    };
+                    STANDARD.decode(value).map_err(|e| {
+                        serde_json::Error::custom(format!(
+                            "invalid base64 value for Any of type {type_url}: {e}"
+                        ))
+                    })?
+                }
+            },
+        };
+        let mut any = DynamicMessage::new(descriptor);
+        any.set_field_by_name("type_url", Value::String(type_url));
+        any.set_field_by_name("value", Value::Bytes(value.into()));
+        Ok(Some(any))
+    }
+
+    fn resolve(&self, type_url: &str, pool: &DescriptorPool) -> Option<MessageDescriptor> {
+        self.resolver
+            .resolve(type_url)
+            .or_else(|| pool.resolve(type_url))
+    }
+}
+
+fn type_name(type_url: &str) -> &str {
+    type_url.rsplit_once('/').map_or(type_url, |(_, name)| name)
+}
+
+fn any_fields(any: &DynamicMessage) -> (String, Vec<u8>) {
+    let type_url = match any.get_field_by_name("type_url").as_deref() {
+        Some(Value::String(type_url)) => type_url.clone(),
+        _ => String::new(),
+    };
+    let value = match any.get_field_by_name("value").as_deref() {
+        Some(Value::Bytes(value)) => value.to_vec(),
+        _ => Vec::new(),
+    };
+    (type_url, value)
+}
+
+/// Whether the field might contain an `Any` message, directly or in a nested message
+fn reaches_any(field: &FieldDescriptor) -> bool {
+    let Kind::Message(descriptor) = field.kind() else {
+        return false;
+    };
+    let mut visited = HashSet::new();
+    let mut to_visit = vec![descriptor];
+    while let Some(descriptor) = to_visit.pop() {
+        if descriptor.full_name() == ANY {
+            return true;
+        }
+        if visited.insert(descriptor.full_name().to_owned()) {
+            to_visit.extend(descriptor.fields().filter_map(|field| match field.kind() {
+                Kind::Message(descriptor) => Some(descriptor),
+                _ => None,
+            }));
+        }
+    }
+    false
+}
+
+fn map_key_to_string(key: &MapKey) -> String {
+    match key {
+        MapKey::Bool(key) => key.to_string(),
+        MapKey::I32(key) => key.to_string(),
+        MapKey::I64(key) => key.to_string(),
+        MapKey::U32(key) => key.to_string(),
+        MapKey::U64(key) => key.to_string(),
+        MapKey::String(key) => key.clone(),
+    }
+}
+
+fn map_key_from_string(kind: &Kind, key: &str) -> Result<MapKey, serde_json::Error> {
+    let map_key = match kind {
+        Kind::Bool => key.parse().ok().map(MapKey::Bool),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => key.parse().ok().map(MapKey::I32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => key.parse().ok().map(MapKey::I64),
+        Kind::Uint32 | Kind::Fixed32 => key.parse().ok().map(MapKey::U32),
+        Kind::Uint64 | Kind::Fixed64 => key.parse().ok().map(MapKey::U64),
+        Kind::String => Some(MapKey::String(key.into())),
+        _ => None,
+    };
+    map_key.ok_or_else(|| serde_json::Error::custom(format!("invalid map key {key}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::prost::encoding;
+    use prost_reflect::prost_types::{
+        Any, DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        field_descriptor_proto,
+    };
+
+    fn pool() -> DescriptorPool {
+        let any_field =
+            |name: &str, number, label: field_descriptor_proto::Label| FieldDescriptorProto {
+                name: Some(name.into()),
+                number: Some(number),
+                label: Some(label.into()),
+                r#type: Some(field_descriptor_proto::Type::Message.into()),
+                type_name: Some(".google.protobuf.Any".into()),
+                json_name: Some(name.into()),
+                ..Default::default()
+            };
+        let mut pool = DescriptorPool::new();
+        pool.add_file_descriptor_set(FileDescriptorSet {
+            file: vec![
+                Any::default()
+                    .descriptor()
+                    .parent_file_descriptor_proto()
+                    .clone(),
+                FileDescriptorProto {
+                    name: Some("test.proto".into()),
+                    package: Some("test".into()),
+                    dependency: vec!["google/protobuf/any.proto".into()],
+                    message_type: vec![
+                        DescriptorProto {
+                            name: Some("Message".into()),
+                            field: vec![
+                                any_field("value", 1, field_descriptor_proto::Label::Optional),
+                                any_field("values", 2, field_descriptor_proto::Label::Repeated),
+                            ],
+                            ..Default::default()
+                        },
+                        DescriptorProto {
+                            name: Some("Int".into()),
+                            field: vec![FieldDescriptorProto {
+                                name: Some("value".into()),
+                                number: Some(1),
+                                label: Some(field_descriptor_proto::Label::Optional.into()),
+                                r#type: Some(field_descriptor_proto::Type::Int32.into()),
+                                json_name: Some("value".into()),
+                                ..Default::default()
+                            }],
+                            ..Default::default()
+                        },
+                    ],
+                    syntax: Some("proto3".into()),
+                    ..Default::default()
+                },
+            ],
+        })
+        .unwrap();
+        pool
+    }
+
+    fn message(pool: &DescriptorPool) -> DynamicMessage {
+        let known = Any {
+            type_url: "type.googleapis.com/test.Message".into(),
+            value: Vec::new(),
+        };
+        let unknown = Any {
+            type_url: "type.googleapis.com/other.Message".into(),
+            value: vec![8, 1],
+        };
+        let mut buffer = Vec::new();
+        encoding::message::encode(1, &unknown, &mut buffer);
+        encoding::message::encode(2, &known, &mut buffer);
+        encoding::message::encode(2, &unknown, &mut buffer);
+        DynamicMessage::decode(
+            pool.get_message_by_name("test.Message").unwrap(),
+            buffer.as_slice(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn unknown_any_error() {
+        let pool = pool();
+        let resolver = AnyResolver::new(DescriptorPool::new());
+        assert!(resolver.encode_json(&message(&pool)).is_err());
+        assert!(
+            resolver
+                .decode_json(
+                    pool.get_message_by_name("test.Message").unwrap(),
+                    br#"{"value":{"@type":"type.googleapis.com/other.Message"}}"#
+                )
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn unknown_any_skip() {
+        let pool = pool();
+        let resolver = AnyResolver::new(DescriptorPool::new()).unknown_any(UnknownAny::Skip);
+        let json = resolver.encode_json(&message(&pool)).unwrap();
+        assert_eq!(
+            json.to_string(),
+            r#"{"values":[{"@type":"type.googleapis.com/test.Message"}]}"#
+        );
+        let decoded = resolver
+            .decode_json(
+                pool.get_message_by_name("test.Message").unwrap(),
+                json.to_string().as_bytes(),
+            )
+            .unwrap();
+        assert!(!decoded.has_field_by_name("value"));
+    }
+
+    #[test]
+    fn unknown_any_base64() {
+        let pool = pool();
+        let resolver = AnyResolver::new(DescriptorPool::new()).unknown_any(UnknownAny::Base64);
+        let message = message(&pool);
+        let json = resolver.encode_json(&message).unwrap();
+        assert_eq!(
+            json.to_string(),
+            r#"{"value":{"@type":"type.googleapis.com/other.Message","value":"CAE="},"values":[{"@type":"type.googleapis.com/test.Message"},{"@type":"type.googleapis.com/other.Message","value":"CAE="}]}"#
+        );
+        let decoded = resolver
+            .decode_json(message.descriptor(), json.to_string().as_bytes())
+            .unwrap();
+        assert_eq!(decoded.encode_to_vec(), message.encode_to_vec());
+    }
+
+    #[test]
+    fn custom_resolver() {
+        let pool = pool();
+        let resolver_pool = pool.clone();
+        let resolver = AnyResolver::new(move |type_url: &str| {
+            (type_url == "type.googleapis.com/other.Message")
+                .then(|| resolver_pool.get_message_by_name("test.Int"))
+                .flatten()
+        });
+        let message = message(&pool);
+        let json = resolver.encode_json(&message).unwrap();
+        assert_eq!(
+            json["value"].to_string(),
+            r#"{"@type":"type.googleapis.com/other.Message","value":1}"#
+        );
+        let decoded = resolver
+            .decode_json(message.descriptor(), json.to_string().as_bytes())
+            .unwrap();
+        assert_eq!(decoded.encode_to_vec(), message.encode_to_vec());
+    }
+}
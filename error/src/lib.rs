@@ -6,6 +6,12 @@
 )]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+#[cfg(feature = "prost-reflect")]
+pub mod any;
+#[cfg(feature = "prost-reflect")]
+pub mod registry;
+pub mod request_id;
+
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
@@ -0,0 +1,150 @@
+use prost_reflect::{
+    DescriptorError, DescriptorPool, DynamicMessage, MessageDescriptor, ReflectMessage,
+};
+
+/// Registry merging the descriptor pools of several crates to resolve `google.protobuf.Any` in JSON.
+///
+/// Each `twurst-build` build scope has its own `DESCRIPTOR_POOL`,
+/// so by default the JSON encoding of an `Any` field only knows the types of the crate defining the message.
+/// When messages from other crates are packed into `Any` fields, register all the relevant pools
+/// and set the registry with `TwirpHttpClient::set_descriptor_registry` in `twurst-client`
+/// or add it to the request extensions, e.g. with an `axum::Extension` layer, in `twurst-server`.
+///
+/// Pools are merged in registration order: if two pools define a file with the same name, the first one wins.
+///
+/// ```
+/// use prost_reflect::DescriptorPool;
+/// use twurst_error::registry::DescriptorRegistry;
+///
+/// # fn main() -> Result<(), prost_reflect::DescriptorError> {
+/// # let first_crate_pool = DescriptorPool::global();
+/// # let second_crate_pool = DescriptorPool::global();
+/// let _registry = DescriptorRegistry::new()
+///     .with_pool(&first_crate_pool)?
+///     .with_pool(&second_crate_pool)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DescriptorRegistry {
+    pool: DescriptorPool,
+}
+
+impl DescriptorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add all the files of a descriptor pool to the registry
+    pub fn with_pool(mut self, pool: &DescriptorPool) -> Result<Self, DescriptorError> {
+        self.add_pool(pool)?;
+        Ok(self)
+    }
+
+    /// Add all the files of a descriptor pool to the registry
+    pub fn add_pool(&mut self, pool: &DescriptorPool) -> Result<(), DescriptorError> {
+        // We go through the encoded form to keep the custom options
+        self.pool
+            .decode_file_descriptor_set(pool.encode_to_vec().as_slice())
+    }
+
+    /// The merged descriptor pool
+    pub fn pool(&self) -> &DescriptorPool {
+        &self.pool
+    }
+
+    /// The descriptor of the message type from the registry if registered
+    pub fn descriptor<T: ReflectMessage + Default>(&self) -> MessageDescriptor {
+        let descriptor = T::default().descriptor();
+        self.pool
+            .get_message_by_name(descriptor.full_name())
+            .unwrap_or(descriptor)
+    }
+
+    /// Converts the message to a dynamic message using the registry descriptors if the type is registered
+    pub fn transcode_to_dynamic<T: ReflectMessage>(&self, message: &T) -> DynamicMessage {
+        if let Some(descriptor) = self
+            .pool
+            .get_message_by_name(message.descriptor().full_name())
+            && let Ok(dynamic_message) =
+                DynamicMessage::decode(descriptor, message.encode_to_vec().as_slice())
+        {
+            return dynamic_message;
+        }
+        message.transcode_to_dynamic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::prost::encoding;
+    use prost_reflect::prost_types::{
+        Any, DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        field_descriptor_proto,
+    };
+
+    fn pool(package: &str) -> DescriptorPool {
+        let mut pool = DescriptorPool::new();
+        pool.add_file_descriptor_set(FileDescriptorSet {
+            file: vec![
+                Any::default()
+                    .descriptor()
+                    .parent_file_descriptor_proto()
+                    .clone(),
+                FileDescriptorProto {
+                    name: Some(format!("{package}.proto")),
+                    package: Some(package.into()),
+                    dependency: vec!["google/protobuf/any.proto".into()],
+                    message_type: vec![DescriptorProto {
+                        name: Some("Message".into()),
+                        field: vec![FieldDescriptorProto {
+                            name: Some("value".into()),
+                            number: Some(1),
+                            label: Some(field_descriptor_proto::Label::Optional.into()),
+                            r#type: Some(field_descriptor_proto::Type::Message.into()),
+                            type_name: Some(".google.protobuf.Any".into()),
+                            json_name: Some("value".into()),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    }],
+                    syntax: Some("proto3".into()),
+                    ..Default::default()
+                },
+            ],
+        })
+        .unwrap();
+        pool
+    }
+
+    #[test]
+    fn resolves_any_across_pools() {
+        let first = pool("first");
+        let second = pool("second");
+        let any = Any {
+            type_url: "type.googleapis.com/second.Message".into(),
+            value: Vec::new(),
+        };
+        let mut buffer = Vec::new();
+        encoding::message::encode(1, &any, &mut buffer);
+        let outer = DynamicMessage::decode(
+            first.get_message_by_name("first.Message").unwrap(),
+            buffer.as_slice(),
+        )
+        .unwrap();
+
+        // Without registry the type is unknown
+        assert!(serde_json::to_string(&outer.transcode_to_dynamic()).is_err());
+
+        let registry = DescriptorRegistry::new()
+            .with_pool(&first)
+            .unwrap()
+            .with_pool(&second)
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&registry.transcode_to_dynamic(&outer)).unwrap(),
+            r#"{"value":{"@type":"type.googleapis.com/second.Message"}}"#
+        );
+    }
+}
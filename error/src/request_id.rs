@@ -0,0 +1,103 @@
+//! Propagation of request IDs between Twirp servers and clients.
+//!
+//! `twurst-server` sets the ID of the request being processed with [`scope`]
+//! and `twurst-client` forwards the [`current`] one in the [`REQUEST_ID_HEADER`] header of outgoing calls.
+
+use pin_project_lite::pin_project;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Name of the HTTP header holding the request ID
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+thread_local! {
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The request ID set by the enclosing [`scope`], if any
+pub fn current() -> Option<String> {
+    CURRENT.with_borrow(Clone::clone)
+}
+
+/// Runs the future with `request_id` as [`current`] request ID.
+///
+/// The request ID is only set while the future itself is polled: it is not inherited by the tasks spawned from the future
+/// nor set while the streams it returns (e.g. the bodies of streamed gRPC responses) are polled.
+///
+/// ```
+/// use twurst_error::request_id;
+///
+/// let future = request_id::scope("foo", async { request_id::current() });
+/// # let _ = future;
+/// ```
+pub fn scope<F: Future>(request_id: impl Into<String>, future: F) -> Scope<F> {
+    Scope {
+        request_id: Some(request_id.into()),
+        future,
+    }
+}
+
+pin_project! {
+    /// Future returned by [`scope`]
+    pub struct Scope<F> {
+        request_id: Option<String>,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F: Future> Future for Scope<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.project();
+        let _guard = Guard::enter(this.request_id);
+        this.future.poll(cx)
+    }
+}
+
+/// Swaps the request ID in and out of the thread local, even on panic
+struct Guard<'a> {
+    request_id: &'a mut Option<String>,
+}
+
+impl<'a> Guard<'a> {
+    fn enter(request_id: &'a mut Option<String>) -> Self {
+        CURRENT.with_borrow_mut(|current| std::mem::swap(current, request_id));
+        Self { request_id }
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        CURRENT.with_borrow_mut(|current| std::mem::swap(current, self.request_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::pin;
+    use std::task::Waker;
+
+    #[test]
+    fn nested_scopes() {
+        let mut future = pin!(scope("outer", async {
+            let outer = current();
+            let inner = scope("inner", async { current() }).await;
+            (outer, inner, current())
+        }));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(
+            future.as_mut().poll(&mut cx),
+            Poll::Ready((
+                Some("outer".into()),
+                Some("inner".into()),
+                Some("outer".into())
+            ))
+        );
+        assert_eq!(current(), None);
+    }
+}
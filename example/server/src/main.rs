@@ -1,10 +1,12 @@
 use crate::proto::{ExampleService, TestRequest, TestResponse};
 use axum::Router;
 use axum::http::HeaderMap;
+use prost_reflect::ReflectMessage;
 use std::error::Error;
 use tokio::join;
 use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
+use twurst_server::playground::Playground;
 use twurst_server::{TwirpError, grpc_fallback, twirp_fallback};
 
 pub mod proto {
@@ -28,7 +30,8 @@ impl ExampleService for ExampleServiceServicer {
 
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn Error>> {
-    // We expose Twirp with CORS enabled
+    // We expose Twirp with CORS enabled and a playground to call it from a browser
+    let pool = TestRequest::default().descriptor().parent_pool().clone();
     let twirp_service = axum::serve(
         TcpListener::bind("localhost:8080").await?,
         Router::new()
@@ -38,6 +41,10 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
                     .into_router()
                     .fallback(twirp_fallback),
             )
+            .nest(
+                "/playground",
+                Playground::new(pool).twirp_prefix("/twirp").into_router(),
+            )
             .layer(
                 CorsLayer::new()
                     .allow_methods(Any)
@@ -4,17 +4,26 @@ use std::{env, fs};
 fn main() -> std::io::Result<()> {
     twurst_build::TwirpBuilder::new()
         .with_client()
+        .with_grpc_client()
+        .with_blocking_client()
         .with_server()
         .with_grpc()
+        .with_dyn_server()
+        .with_default_unimplemented_methods()
         .with_default_axum_request_extractor("bearer_token", "crate::server::ExtractBearerToken")
-        .compile_protos(&["integration.proto"], &["."])?;
-
-    // Custom out dir to not override Twirp
-    let tonic_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("tonic");
-    fs::create_dir_all(&tonic_dir)?;
-    tonic_prost_build::configure()
-        .out_dir(&tonic_dir)
-        .type_attribute("Int", "#[allow(dead_code)]") // to make clippy happy
+        .with_method_specific_axum_request_extractor(
+            "bearer_token",
+            "crate::server::ExtractBearerToken",
+            ".integration.IntegrationService.Test",
+        )
+        .with_method_specific_axum_request_extractor(
+            "headers",
+            "::axum::http::HeaderMap",
+            "IntegrationService.Test",
+        )
+        .with_typescript_client(
+            PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("integration.ts"),
+        )
         .compile_protos(&["integration.proto"], &["."])?;
 
     // Custom out dir with skip_prost_reflect: caller configures prost-reflect externally
@@ -31,5 +40,19 @@ fn main() -> std::io::Result<()> {
         .with_server()
         .compile_protos(&["integration.proto"], &["."])?;
 
+    // Generated from the precompiled descriptor set written above, without the .proto sources
+    let from_fds_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("from_fds");
+    fs::create_dir_all(&from_fds_dir)?;
+    let mut from_fds_config = twurst_build::prost::Config::new();
+    from_fds_config.out_dir(&from_fds_dir).skip_protoc_run();
+    prost_reflect_build::Builder::new()
+        .file_descriptor_set_path(custom_dir.join("file_descriptor_set.bin"))
+        .descriptor_pool("crate::from_fds::DESCRIPTOR_POOL")
+        .configure(&mut from_fds_config, &[] as &[&str], &[] as &[&str])?;
+    twurst_build::TwirpBuilder::from_prost(from_fds_config)
+        .skip_prost_reflect()
+        .with_client()
+        .compile_fds_from_path(custom_dir.join("file_descriptor_set.bin"))?;
+
     Ok(())
 }
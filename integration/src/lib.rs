@@ -18,5 +18,20 @@ pub mod custom_out_dir {
     include!(concat!(env!("OUT_DIR"), "/custom/integration.rs"));
 }
 
+/// Generated from a precompiled `FileDescriptorSet` with `compile_fds_from_path`.
+pub mod from_fds {
+    use prost_reflect::DescriptorPool;
+    use std::sync::LazyLock;
+
+    static DESCRIPTOR_POOL: LazyLock<DescriptorPool> = LazyLock::new(|| {
+        DescriptorPool::decode(
+            include_bytes!(concat!(env!("OUT_DIR"), "/custom/file_descriptor_set.bin")).as_ref(),
+        )
+        .expect("failed to decode descriptor pool")
+    });
+
+    include!(concat!(env!("OUT_DIR"), "/from_fds/integration.rs"));
+}
+
 pub mod client;
 pub mod server;
@@ -1,6 +1,7 @@
 use crate::proto::{IntegrationService, TestRequest, TestResponse, test_request, test_response};
 use axum::Router;
 use axum::extract::FromRequestParts;
+use axum::http::HeaderMap;
 use axum::http::header::AUTHORIZATION;
 use axum::http::request::Parts;
 use eyre::Result;
@@ -19,10 +20,16 @@ impl IntegrationService for IntegrationServiceServicer {
         &self,
         request: TestRequest,
         ExtractBearerToken(bearer_token): ExtractBearerToken,
+        headers: HeaderMap,
     ) -> Result<TestResponse, TwirpError> {
         if bearer_token != "password" {
             return Err(TwirpError::unauthenticated("Invalid password"));
         }
+        if let Some(error) = headers.get("x-test-error") {
+            return Err(TwirpError::invalid_argument(
+                String::from_utf8_lossy(error.as_bytes()).into_owned(),
+            ));
+        }
         Ok(response_from_request(request))
     }
 
@@ -122,6 +129,23 @@ pub async fn serve_twirp() -> Result<Server> {
     Ok(Server { url, task })
 }
 
+#[cfg(unix)]
+pub async fn serve_twirp_unix(path: &std::path::Path) -> Result<Server> {
+    let listener = tokio::net::UnixListener::bind(path)?;
+    let task = spawn(async move {
+        axum::serve(
+            listener,
+            Router::new().nest("/twirp", IntegrationServiceServicer {}.into_router()),
+        )
+        .await
+        .unwrap();
+    });
+    Ok(Server {
+        url: "http://localhost/twirp".into(),
+        task,
+    })
+}
+
 pub async fn serve_grpc() -> Result<Server> {
     let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
     let url = format!("http://{}", listener.local_addr()?);
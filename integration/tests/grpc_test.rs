@@ -1,26 +1,34 @@
-use crate::proto::integration_service_client::IntegrationServiceClient;
-use crate::proto::test_nested::NestedMessage;
-use crate::proto::{TestNested, TestRequest, TestResponse, test_request, test_response};
 use eyre::Result;
 use prost_types::Value;
 use std::time::{Duration, UNIX_EPOCH};
 use tokio_stream::StreamExt;
-use tonic::{Code, Request};
+use tonic::transport::Channel;
+use tower::ServiceBuilder;
+use tower_http::auth::{AddAuthorization, AddAuthorizationLayer};
+use twurst_integration::proto::test_nested::NestedMessage;
+use twurst_integration::proto::{
+    IntegrationServiceGrpcClient, TestNested, TestRequest, TestResponse, test_request,
+    test_response,
+};
 use twurst_integration::server;
+use twurst_server::{TwirpError, TwirpErrorCode};
 
-mod proto {
-    tonic::include_proto!("tonic/integration");
+async fn authenticated_client(
+    url: &str,
+) -> Result<IntegrationServiceGrpcClient<AddAuthorization<Channel>>> {
+    let channel = Channel::from_shared(url.to_string())?.connect().await?;
+    Ok(IntegrationServiceGrpcClient::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(channel),
+    ))
 }
 
 #[tokio::test]
 async fn test_simple_grpc_echo() -> Result<()> {
     let server = server::serve_grpc().await?;
-    let mut client = IntegrationServiceClient::connect(server.url().to_string()).await?;
-    let mut request = Request::new(dummy_request());
-    request
-        .metadata_mut()
-        .insert("authorization", "Bearer password".parse()?);
-    let response = client.test(request).await?.into_inner();
+    let client = authenticated_client(server.url()).await?;
+    let response = client.test(&dummy_request()).await?;
     assert_eq!(response, dummy_response());
     Ok(())
 }
@@ -28,41 +36,43 @@ async fn test_simple_grpc_echo() -> Result<()> {
 #[tokio::test]
 async fn test_no_authorization_header() -> Result<()> {
     let server = server::serve_grpc().await?;
-    let mut client = IntegrationServiceClient::connect(server.url().to_string()).await?;
-    let status = client.test(TestRequest::default()).await.unwrap_err();
-    assert_eq!(status.code(), Code::Unauthenticated);
-    assert_eq!(status.message(), "Authorization header is required");
+    let client = IntegrationServiceGrpcClient::new(
+        Channel::from_shared(server.url().to_string())?
+            .connect()
+            .await?,
+    );
+    let error = client.test(&TestRequest::default()).await.unwrap_err();
+    assert_eq!(
+        error,
+        TwirpError::unauthenticated("Authorization header is required")
+    );
     Ok(())
 }
 
 #[tokio::test]
 async fn test_server_streaming_grpc_echo() -> Result<()> {
     let server = server::serve_grpc().await?;
-    let mut client = IntegrationServiceClient::connect(server.url().to_string()).await?;
-    let mut request = Request::new(dummy_request());
-    request
-        .metadata_mut()
-        .insert("authorization", "Bearer password".parse()?);
+    let client = authenticated_client(server.url()).await?;
     let response = client
-        .test_server_stream(request)
+        .test_server_stream(&dummy_request())
         .await?
-        .into_inner()
         .collect::<Vec<_>>()
         .await;
     assert_eq!(response[0].clone()?, dummy_response());
-    assert_eq!(response[1].clone().unwrap_err().code(), Code::NotFound);
+    assert_eq!(
+        response[1].clone().unwrap_err().code(),
+        TwirpErrorCode::NotFound
+    );
     Ok(())
 }
 
 #[tokio::test]
 async fn test_client_streaming_grpc_echo() -> Result<()> {
     let server = server::serve_grpc().await?;
-    let mut client = IntegrationServiceClient::connect(server.url().to_string()).await?;
-    let mut request = Request::new(tokio_stream::once(dummy_request()));
-    request
-        .metadata_mut()
-        .insert("authorization", "Bearer password".parse()?);
-    let response = client.test_client_stream(request).await?.into_inner();
+    let client = authenticated_client(server.url()).await?;
+    let response = client
+        .test_client_stream(tokio_stream::once(dummy_request()))
+        .await?;
     assert_eq!(response, dummy_response());
     Ok(())
 }
@@ -70,15 +80,10 @@ async fn test_client_streaming_grpc_echo() -> Result<()> {
 #[tokio::test]
 async fn test_streaming_grpc_echo() -> Result<()> {
     let server = server::serve_grpc().await?;
-    let mut client = IntegrationServiceClient::connect(server.url().to_string()).await?;
-    let mut request = Request::new(tokio_stream::once(dummy_request()));
-    request
-        .metadata_mut()
-        .insert("authorization", "Bearer password".parse()?);
+    let client = authenticated_client(server.url()).await?;
     let response = client
-        .test_stream(request)
+        .test_stream(tokio_stream::once(dummy_request()))
         .await?
-        .into_inner()
         .collect::<Vec<_>>()
         .await;
     assert_eq!(response[0].clone()?, dummy_response());
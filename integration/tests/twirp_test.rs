@@ -1,13 +1,27 @@
+use axum::Extension;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::from_fn;
 use eyre::Result;
+use prost_reflect::ReflectMessage;
+use prost_types::Any;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use tower::ServiceBuilder;
 use tower_http::auth::AddAuthorizationLayer;
-use twurst_client::TwirpHttpClient;
+use twurst_client::balance::BalancedService;
+use twurst_client::{
+    AnyResolver, DescriptorRegistry, Hyper1Service, TwirpErrorCode, TwirpHttpClient, UnknownAny,
+};
 use twurst_integration::client::{Choice, Data, IntegrationClient};
-use twurst_integration::proto::{IntegrationService, IntegrationServiceClient};
+use twurst_integration::from_fds;
+use twurst_integration::proto::{
+    DynIntegrationService, IntegrationService, IntegrationServiceBlockingClient,
+    IntegrationServiceClient, Login, Role, TestRequest, login,
+};
 use twurst_integration::server;
 use twurst_integration::server::IntegrationServiceServicer;
-use twurst_server::TwirpError;
+use twurst_server::{TwirpError, TwirpErrorMapper, redacted, request_id};
 
 #[tokio::test]
 async fn test_simple_twirp_echo_protobuf() -> Result<()> {
@@ -29,6 +43,117 @@ async fn test_simple_twirp_echo_json() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_simple_twirp_echo_hyper_1() -> Result<()> {
+    let server = server::serve_twirp().await?;
+    let data = example_data();
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new_with_base(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(Hyper1Service::new()),
+        server.url(),
+    ));
+    let response = Data::try_from(client.test(&data.clone().try_into()?).await?)?;
+    assert_eq!(response, data);
+    Ok(())
+}
+
+#[test]
+fn test_blocking_client() -> Result<()> {
+    // The server runs on its own runtime, the blocking client must be used outside of it
+    let (url_sender, url_receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let server = server::serve_twirp().await.unwrap();
+                url_sender.send(server.url().to_owned()).unwrap();
+                std::future::pending::<()>().await;
+            })
+    });
+    let data = example_data();
+    let client = IntegrationServiceBlockingClient::new(TwirpHttpClient::new_with_base(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(Hyper1Service::new()),
+        url_receiver.recv()?,
+    ))?;
+    let response = client.test_with_metadata(&data.clone().try_into()?)?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(Data::try_from(response.into_message())?, data);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_simple_twirp_echo_unix_socket() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    for http2 in [false, true] {
+        let path = dir.path().join(format!("{http2}.sock"));
+        let server = server::serve_twirp_unix(&path).await?;
+        let builder = if http2 {
+            Hyper1Service::builder().http2_prior_knowledge()
+        } else {
+            Hyper1Service::builder()
+        };
+        let data = example_data();
+        let client = IntegrationServiceClient::new(TwirpHttpClient::new_with_base(
+            ServiceBuilder::new()
+                .layer(AddAuthorizationLayer::bearer("password"))
+                .service(builder.build_unix(&path)),
+            server.url(),
+        ));
+        let response = Data::try_from(client.test(&data.clone().try_into()?).await?)?;
+        assert_eq!(response, data);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_simple_twirp_echo_hyper_1_http2() -> Result<()> {
+    let server = server::serve_twirp().await?;
+    let data = example_data();
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new_with_base(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(
+                Hyper1Service::builder()
+                    .http2_prior_knowledge()
+                    .pool_max_idle_per_host(1)
+                    .build_http(),
+            ),
+        server.url(),
+    ));
+    let response = Data::try_from(client.test(&data.clone().try_into()?).await?)?;
+    assert_eq!(response, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_balanced_with_ejection() -> Result<()> {
+    let server = server::serve_twirp().await?;
+    let data = example_data();
+    let balanced = BalancedService::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(Hyper1Service::new()),
+        ["http://127.0.0.1:1", server.url()],
+    )
+    .eject_after(1, Duration::from_secs(60));
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(balanced.clone()));
+    // The first call goes to the unreachable endpoint that is then ejected
+    let error = client.test(&data.clone().try_into()?).await.unwrap_err();
+    assert_eq!(error.code(), TwirpErrorCode::Unknown);
+    assert_eq!(balanced.available_base_urls(), [server.url()]);
+    for _ in 0..3 {
+        let response = Data::try_from(client.test(&data.clone().try_into()?).await?)?;
+        assert_eq!(response, data);
+    }
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_without_networking() -> Result<()> {
     let data = example_data();
@@ -42,6 +167,224 @@ async fn test_without_networking() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_dyn_service() -> Result<()> {
+    let data = example_data();
+    let service: Arc<dyn DynIntegrationService> = Arc::new(IntegrationServiceServicer {});
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(service.into_router()),
+    ));
+    let response = Data::try_from(client.test(&data.clone().try_into()?).await?)?;
+    assert_eq!(response, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_response_metadata() -> Result<()> {
+    let data = example_data();
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(IntegrationServiceServicer {}.into_router()),
+    ));
+    let response = client.test_with_metadata(&data.clone().try_into()?).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE),
+        Some(&HeaderValue::from_static("application/protobuf"))
+    );
+    assert_eq!(Data::try_from(response.into_message())?, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_method_builder() -> Result<()> {
+    let data = example_data();
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(
+        IntegrationServiceServicer {}.into_router(),
+    ));
+    let response = client
+        .test_builder(&data.clone().try_into()?)
+        .header(AUTHORIZATION, "Bearer password")
+        .timeout(Duration::from_secs(10))
+        .use_json()
+        .send_with_metadata()
+        .await?;
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE),
+        Some(&HeaderValue::from_static("application/json"))
+    );
+    assert_eq!(Data::try_from(response.into_message())?, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_method_specific_extractor() -> Result<()> {
+    let data = example_data();
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(
+        IntegrationServiceServicer {}.into_router(),
+    ));
+    let error = client
+        .test_builder(&data.try_into()?)
+        .header(AUTHORIZATION, "Bearer password")
+        .header("x-test-error", "boom")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error, TwirpError::invalid_argument("boom"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_json_with_descriptor_registry() -> Result<()> {
+    let data = example_data();
+    let server_registry = twurst_server::DescriptorRegistry::new()
+        .with_pool(from_fds::TestRequest::default().descriptor().parent_pool())?;
+    let mut client = TwirpHttpClient::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(
+                IntegrationServiceServicer {}
+                    .into_router()
+                    .layer(Extension(server_registry)),
+            ),
+    );
+    client.use_json();
+    client.set_descriptor_registry(
+        DescriptorRegistry::new()
+            .with_pool(from_fds::TestRequest::default().descriptor().parent_pool())?
+            .with_pool(TestRequest::default().descriptor().parent_pool())?,
+    );
+    let client = IntegrationServiceClient::new(client);
+    let response = Data::try_from(client.test(&data.clone().try_into()?).await?)?;
+    assert_eq!(response, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_json_relays_unknown_any() -> Result<()> {
+    let resolver = AnyResolver::new(DescriptorRegistry::new()).unknown_any(UnknownAny::Base64);
+    let mut client = TwirpHttpClient::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(
+                IntegrationServiceServicer {}.into_router().layer(Extension(
+                    twurst_server::AnyResolver::new(twurst_server::DescriptorRegistry::new())
+                        .unknown_any(twurst_server::UnknownAny::Base64),
+                )),
+            ),
+    );
+    client.use_json();
+    client.set_any_resolver(resolver);
+    let client = IntegrationServiceClient::new(client);
+    let any = Any {
+        type_url: "type.googleapis.com/unknown.Type".into(),
+        value: vec![8, 1],
+    };
+    let response = client
+        .test(&TestRequest {
+            any: Some(any.clone()),
+            ..Default::default()
+        })
+        .await?;
+    assert_eq!(response.any, Some(any));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_error_mapper() -> Result<()> {
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(
+        IntegrationServiceServicer {}
+            .into_router()
+            .layer(Extension(TwirpErrorMapper::new(|error| {
+                TwirpError::new(error.code(), "redacted")
+            }))),
+    ));
+    // Error returned by the method
+    let error = client
+        .test_builder(&example_data().try_into()?)
+        .header(AUTHORIZATION, "Bearer password")
+        .header("x-test-error", "boom")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error, TwirpError::invalid_argument("redacted"));
+    // Error returned by an extractor
+    let error = client.test(&example_data().try_into()?).await.unwrap_err();
+    assert_eq!(error, TwirpError::unauthenticated("redacted"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_request_id() -> Result<()> {
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(
+        IntegrationServiceServicer {}
+            .into_router()
+            .layer(from_fn(request_id::middleware)),
+    ));
+    let response = client
+        .test_builder(&example_data().try_into()?)
+        .header(AUTHORIZATION, "Bearer password")
+        .header("x-request-id", "test-id")
+        .send_with_metadata()
+        .await?;
+    assert_eq!(
+        response.headers().get("x-request-id"),
+        Some(&HeaderValue::from_static("test-id"))
+    );
+    let error = client
+        .test_builder(&example_data().try_into()?)
+        .header(AUTHORIZATION, "Bearer password")
+        .header("x-request-id", "test-id")
+        .header("x-test-error", "boom")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.meta("request_id"), Some("test-id"));
+    // A request ID is generated if missing
+    let error = client.test(&example_data().try_into()?).await.unwrap_err();
+    assert!(error.meta("request_id").is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_from_fds() -> Result<()> {
+    let client = from_fds::IntegrationServiceClient::new(TwirpHttpClient::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(IntegrationServiceServicer {}.into_router()),
+    ));
+    let response = client
+        .test(&from_fds::TestRequest {
+            string: "foo".into(),
+            ..Default::default()
+        })
+        .await?;
+    assert_eq!(response.string, "foo");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_default_unimplemented_method() -> Result<()> {
+    struct EmptyServicer;
+
+    impl IntegrationService for EmptyServicer {}
+
+    let client = IntegrationServiceClient::new(TwirpHttpClient::new(
+        ServiceBuilder::new()
+            .layer(AddAuthorizationLayer::bearer("password"))
+            .service(EmptyServicer.into_router()),
+    ));
+    let error = client.test(&example_data().try_into()?).await.unwrap_err();
+    assert_eq!(
+        error,
+        TwirpError::unimplemented("/integration.IntegrationService/Test is not implemented")
+    );
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_no_auth_header() -> Result<()> {
     let data = example_data();
@@ -69,6 +412,28 @@ async fn test_wrong_password() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_debug_redact() {
+    let login = Login {
+        user: "alice".into(),
+        password: "secret".into(),
+        token: Some(login::Token::ApiKey("secret".into())),
+        role: Role::Admin.into(),
+        previous_role: Some(42),
+        roles: vec![Role::Admin.into(), 42],
+        project_roles: [("foo".into(), Role::Admin.into())].into(),
+    };
+    assert_eq!(
+        format!("{login:?}"),
+        r#"Login { user: "alice", password: [REDACTED], role: Admin, previous_role: Some(42), roles: [Admin, 42], project_roles: {"foo": Admin}, token: Some(ApiKey([REDACTED])) }"#
+    );
+    assert_eq!(
+        format!("{:?}", redacted(&login)),
+        r#"Login { user: "alice", password: [REDACTED], api_key: [REDACTED], role: ROLE_ADMIN, previous_role: 42, roles: [ROLE_ADMIN, 42], project_roles: {"foo": ROLE_ADMIN} }"#
+    );
+    assert_eq!(format!("{:?}", login::Token::Session(1)), "Session(1)");
+}
+
 fn example_data() -> Data {
     Data {
         string: "test_simple_twirp_echo".to_string(),
@@ -1,4 +1,5 @@
-use crate::TwirpError;
+use crate::error_mapper::ErrorContext;
+use crate::{AnyResolver, DescriptorRegistry, TwirpError};
 use axum::RequestExt;
 pub use axum::Router;
 use axum::body::Body;
@@ -8,7 +9,7 @@ use axum::extract::{Request, State};
 use axum::http::Method;
 use axum::http::header::CONTENT_TYPE;
 pub use axum::http::request::Parts as RequestParts;
-use axum::http::{HeaderMap, HeaderValue};
+use axum::http::{Extensions, HeaderMap, HeaderValue};
 pub use axum::response::IntoResponse;
 use axum::response::Response;
 use axum::routing::post;
@@ -16,11 +17,12 @@ use http_body_util::BodyExt;
 #[cfg(feature = "grpc")]
 use pin_project_lite::pin_project;
 use prost_reflect::bytes::{Bytes, BytesMut};
-use prost_reflect::{DeserializeOptions, DynamicMessage, ReflectMessage};
+use prost_reflect::{DeserializeOptions, DynamicMessage, MessageDescriptor, ReflectMessage};
 use serde::Serialize;
 use std::future::Future;
-#[cfg(feature = "grpc")]
 use std::pin::Pin;
+#[cfg(any(feature = "grpc", feature = "validate"))]
+use std::sync::Arc;
 #[cfg(feature = "grpc")]
 use std::task::{Context, Poll};
 #[cfg(feature = "grpc")]
@@ -31,6 +33,9 @@ use tracing::error;
 pub use trait_variant::make as trait_variant_make;
 use twurst_error::TwirpErrorCode;
 
+/// Future returned by the methods of the dyn-compatible service traits
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
 const APPLICATION_JSON: HeaderValue = HeaderValue::from_static("application/json");
 const APPLICATION_PROTOBUF: HeaderValue = HeaderValue::from_static("application/protobuf");
 
@@ -57,15 +62,39 @@ impl<S: Clone + Send + Sync + 'static, RS: Clone + Send + Sync + 'static> TwirpR
         call: impl (Fn(S, I, RequestParts, RS) -> F) + Clone + Send + Sync + 'static,
     ) -> Self {
         let service = self.service.clone();
+        #[cfg(feature = "validate")]
+        let validator = crate::validate::Validator::new(&I::default().descriptor()).map(Arc::new);
         self.router = self.router.route(
             path,
             post(
                 move |State(state): State<RS>, request: Request| async move {
-                    let (parts, body) = request.with_limited_body().into_parts();
-                    let content_type = ContentType::from_headers(&parts.headers)?;
-                    let request = parse_request(content_type, body).await?;
-                    let response = call(service, request, parts, state).await?;
-                    serialize_response(content_type, response)
+                    let error_context = ErrorContext::from_extensions(request.extensions());
+                    let result: Result<Response, TwirpError> = async move {
+                        let (parts, body) = request.with_limited_body().into_parts();
+                        let content_type = ContentType::from_headers(&parts.headers)?;
+                        let registry = parts.extensions.get::<DescriptorRegistry>().cloned();
+                        let any_resolver = parts.extensions.get::<AnyResolver>().cloned();
+                        let request = parse_request(
+                            content_type,
+                            body,
+                            registry.as_ref(),
+                            any_resolver.as_ref(),
+                        )
+                        .await?;
+                        #[cfg(feature = "validate")]
+                        if let Some(validator) = &validator {
+                            validator.validate(&request)?;
+                        }
+                        let response = call(service, request, parts, state).await?;
+                        serialize_response(
+                            content_type,
+                            response,
+                            registry.as_ref(),
+                            any_resolver.as_ref(),
+                        )
+                    }
+                    .await;
+                    result.map_err(|e| error_context.map(e))
                 },
             ),
         );
@@ -75,8 +104,10 @@ impl<S: Clone + Send + Sync + 'static, RS: Clone + Send + Sync + 'static> TwirpR
     pub fn route_streaming(mut self, path: &str) -> Self {
         self.router = self.router.route(
             path,
-            post(move || async move {
-                TwirpError::unimplemented("Streaming is not supported by Twirp")
+            post(move |extensions: Extensions| async move {
+                ErrorContext::from_extensions(&extensions).map(TwirpError::unimplemented(
+                    "Streaming is not supported by Twirp",
+                ))
             }),
         );
         self
@@ -114,6 +145,8 @@ impl ContentType {
 async fn parse_request<I: ReflectMessage + Default>(
     content_type: ContentType,
     body: Body,
+    registry: Option<&DescriptorRegistry>,
+    any_resolver: Option<&AnyResolver>,
 ) -> Result<I, TwirpError> {
     let body = body.collect().await.map_err(|e| {
         TwirpError::wrap(
@@ -130,13 +163,15 @@ async fn parse_request<I: ReflectMessage + Default>(
                 e,
             )
         }),
-        ContentType::Json => json_decode(&body.to_bytes()), // TODO: avoid to_bytes?
+        ContentType::Json => json_decode(&body.to_bytes(), registry, any_resolver), // TODO: avoid to_bytes?
     }
 }
 
 fn serialize_response<O: ReflectMessage>(
     content_type: ContentType,
     response: O,
+    registry: Option<&DescriptorRegistry>,
+    any_resolver: Option<&AnyResolver>,
 ) -> Result<Response, TwirpError> {
     let (content_type, body) = match content_type {
         ContentType::Protobuf => {
@@ -150,7 +185,10 @@ fn serialize_response<O: ReflectMessage>(
             })?;
             (APPLICATION_PROTOBUF, buffer.into())
         }
-        ContentType::Json => (APPLICATION_JSON, json_encode(&response)?),
+        ContentType::Json => (
+            APPLICATION_JSON,
+            json_encode(&response, registry, any_resolver)?,
+        ),
     };
     Response::builder()
         .header(CONTENT_TYPE, content_type)
@@ -161,20 +199,43 @@ fn serialize_response<O: ReflectMessage>(
         })
 }
 
-fn json_encode<T: ReflectMessage>(message: &T) -> Result<Bytes, TwirpError> {
+fn json_encode<T: ReflectMessage>(
+    message: &T,
+    registry: Option<&DescriptorRegistry>,
+    any_resolver: Option<&AnyResolver>,
+) -> Result<Bytes, TwirpError> {
+    let dynamic_message = match registry {
+        Some(registry) => registry.transcode_to_dynamic(message),
+        None => message.transcode_to_dynamic(),
+    };
     let mut serializer = serde_json::Serializer::new(Vec::new());
-    message
-        .transcode_to_dynamic()
-        .serialize(&mut serializer)
-        .map_err(|e| {
-            error!("Failed to serialize the JSON response: {e}");
-            TwirpError::internal("Failed to build the response")
-        })?;
+    match any_resolver {
+        Some(any_resolver) => any_resolver
+            .encode_json(&dynamic_message)
+            .and_then(|json| json.serialize(&mut serializer)),
+        None => dynamic_message.serialize(&mut serializer),
+    }
+    .map_err(|e| {
+        error!("Failed to serialize the JSON response: {e}");
+        TwirpError::internal("Failed to build the response")
+    })?;
     Ok(serializer.into_inner().into())
 }
 
-fn json_decode<T: ReflectMessage + Default>(message: &[u8]) -> Result<T, TwirpError> {
-    let dynamic_message = dynamic_json_decode::<T>(message).map_err(|e| {
+fn json_decode<T: ReflectMessage + Default>(
+    message: &[u8],
+    registry: Option<&DescriptorRegistry>,
+    any_resolver: Option<&AnyResolver>,
+) -> Result<T, TwirpError> {
+    let descriptor = match registry {
+        Some(registry) => registry.descriptor::<T>(),
+        None => T::default().descriptor(),
+    };
+    let dynamic_message = match any_resolver {
+        Some(any_resolver) => any_resolver.decode_json(descriptor, message),
+        None => dynamic_json_decode(descriptor, message),
+    }
+    .map_err(|e| {
         TwirpError::wrap(
             TwirpErrorCode::Malformed,
             format!("Invalid JSON protobuf request: {e}"),
@@ -187,12 +248,13 @@ fn json_decode<T: ReflectMessage + Default>(message: &[u8]) -> Result<T, TwirpEr
     })
 }
 
-fn dynamic_json_decode<T: ReflectMessage + Default>(
+fn dynamic_json_decode(
+    descriptor: MessageDescriptor,
     message: &[u8],
 ) -> Result<DynamicMessage, serde_json::Error> {
     let mut deserializer = serde_json::Deserializer::from_slice(message);
     let dynamic_message = DynamicMessage::deserialize_with_options(
-        T::default().descriptor(),
+        descriptor,
         &mut deserializer,
         // Ignore rather than returning an error when unknown fields are present following the proto3 spec:
         // https://protobuf.dev/programming-guides/proto3/#wire-safe-changes
@@ -227,6 +289,8 @@ impl<S: Clone + Send + Sync + 'static> GrpcRouter<S> {
         path: &str,
         callback: C,
     ) -> Self {
+        #[cfg(feature = "validate")]
+        let callback = validated(callback);
         let service = self.service.clone();
         self.router = self.router.route(
             path,
@@ -251,6 +315,8 @@ impl<S: Clone + Send + Sync + 'static> GrpcRouter<S> {
         path: &str,
         callback: C,
     ) -> Self {
+        #[cfg(feature = "validate")]
+        let callback = validated(callback);
         let service = self.service.clone();
         self.router = self.router.route(
             path,
@@ -274,6 +340,8 @@ impl<S: Clone + Send + Sync + 'static> GrpcRouter<S> {
         path: &str,
         callback: C,
     ) -> Self {
+        #[cfg(feature = "validate")]
+        let callback = validated_stream(callback);
         let service = self.service.clone();
         self.router = self.router.route(
             path,
@@ -298,6 +366,8 @@ impl<S: Clone + Send + Sync + 'static> GrpcRouter<S> {
         path: &str,
         callback: C,
     ) -> Self {
+        #[cfg(feature = "validate")]
+        let callback = validated_stream(callback);
         let service = self.service.clone();
         self.router = self.router.route(
             path,
@@ -316,6 +386,51 @@ impl<S: Clone + Send + Sync + 'static> GrpcRouter<S> {
     }
 }
 
+/// Wraps a gRPC callback to validate the requests before calling it
+#[cfg(all(feature = "grpc", feature = "validate"))]
+fn validated<S, I, O, F>(
+    callback: impl (Fn(S, I, RequestParts) -> F) + Clone + Send + Sync + 'static,
+) -> impl (Fn(S, I, RequestParts) -> BoxFuture<'static, Result<O, TwirpError>>)
++ Clone
++ Send
++ Sync
++ 'static
+where
+    S: Send + 'static,
+    I: ReflectMessage + Default + 'static,
+    O: 'static,
+    F: Future<Output = Result<O, TwirpError>> + Send + 'static,
+{
+    let validator = crate::validate::Validator::new(&I::default().descriptor()).map(Arc::new);
+    move |service, request, parts| {
+        let validation = match &validator {
+            Some(validator) => validator.validate(&request),
+            None => Ok(()),
+        };
+        let callback = callback.clone();
+        Box::pin(async move {
+            validation?;
+            callback(service, request, parts).await
+        })
+    }
+}
+
+/// Wraps a gRPC client streaming callback to validate each request item when it is read
+#[cfg(all(feature = "grpc", feature = "validate"))]
+fn validated_stream<S, I, F>(
+    callback: impl (Fn(S, GrpcClientStream<I>, RequestParts) -> F) + Clone + Send + Sync + 'static,
+) -> impl (Fn(S, GrpcClientStream<I>, RequestParts) -> F) + Clone + Send + Sync + 'static
+where
+    I: ReflectMessage + Default + 'static,
+{
+    let check: Option<ItemCheck<I>> = crate::validate::Validator::new(&I::default().descriptor())
+        .map(|validator| Arc::new(move |item: &I| validator.validate(item)) as _);
+    move |service, mut request, parts| {
+        request.check = check.clone();
+        callback(service, request, parts)
+    }
+}
+
 #[cfg(feature = "grpc")]
 struct GrpcService<S, C> {
     service: S,
@@ -336,8 +451,13 @@ impl<
 
     fn call(&mut self, request: tonic::Request<I>) -> Self::Future {
         let (request, parts) = grpc_to_twirp_request(request);
+        let error_context = ErrorContext::from_extensions(&parts.extensions);
         let result_future = (self.callback)(self.service.clone(), request, parts);
-        Box::pin(async move { Ok(tonic::Response::new(result_future.await?)) })
+        Box::pin(async move {
+            Ok(tonic::Response::new(
+                result_future.await.map_err(|e| error_context.map(e))?,
+            ))
+        })
     }
 }
 
@@ -352,17 +472,14 @@ impl<
 > tonic::server::ServerStreamingService<I> for GrpcService<S, C>
 {
     type Response = O;
-    type ResponseStream = Pin<Box<dyn Stream<Item = Result<O, tonic::Status>> + Send>>;
+    type ResponseStream = TonicResponseStream<O>;
     type Future = TonicResponseFuture<Self::ResponseStream>;
 
     fn call(&mut self, request: tonic::Request<I>) -> Self::Future {
         let (request, parts) = grpc_to_twirp_request(request);
+        let error_context = ErrorContext::from_extensions(&parts.extensions);
         let result_future = (self.callback)(self.service.clone(), request, parts);
-        Box::pin(async move {
-            Ok(tonic::Response::new(
-                Box::pin(result_future.await?.map(|item| Ok(item?))) as Self::ResponseStream,
-            ))
-        })
+        Box::pin(mapped_stream_response(result_future, error_context))
     }
 }
 
@@ -380,9 +497,17 @@ impl<
 
     fn call(&mut self, request: tonic::Request<tonic::Streaming<I>>) -> Self::Future {
         let (request, parts) = grpc_to_twirp_request(request);
-        let request = GrpcClientStream { stream: request };
+        let error_context = ErrorContext::from_extensions(&parts.extensions);
+        let request = GrpcClientStream {
+            stream: request,
+            check: None,
+        };
         let result_future = (self.callback)(self.service.clone(), request, parts);
-        Box::pin(async move { Ok(tonic::Response::new(result_future.await?)) })
+        Box::pin(async move {
+            Ok(tonic::Response::new(
+                result_future.await.map_err(|e| error_context.map(e))?,
+            ))
+        })
     }
 }
 
@@ -397,21 +522,39 @@ impl<
 > tonic::server::StreamingService<I> for GrpcService<S, C>
 {
     type Response = O;
-    type ResponseStream = Pin<Box<dyn Stream<Item = Result<O, tonic::Status>> + Send>>;
+    type ResponseStream = TonicResponseStream<O>;
     type Future = TonicResponseFuture<Self::ResponseStream>;
 
     fn call(&mut self, request: tonic::Request<tonic::Streaming<I>>) -> Self::Future {
         let (request, parts) = grpc_to_twirp_request(request);
-        let request = GrpcClientStream { stream: request };
+        let error_context = ErrorContext::from_extensions(&parts.extensions);
+        let request = GrpcClientStream {
+            stream: request,
+            check: None,
+        };
         let result_future = (self.callback)(self.service.clone(), request, parts);
-        Box::pin(async move {
-            Ok(tonic::Response::new(
-                Box::pin(result_future.await?.map(|item| Ok(item?))) as Self::ResponseStream,
-            ))
-        })
+        Box::pin(mapped_stream_response(result_future, error_context))
     }
 }
 
+#[cfg(feature = "grpc")]
+type TonicResponseStream<O> = Pin<Box<dyn Stream<Item = Result<O, tonic::Status>> + Send>>;
+
+/// Maps the errors of a streaming response, both the initial one and the ones of the stream items
+#[cfg(feature = "grpc")]
+async fn mapped_stream_response<
+    O: 'static,
+    OS: Stream<Item = Result<O, TwirpError>> + Send + 'static,
+>(
+    result_future: impl Future<Output = Result<OS, TwirpError>>,
+    error_context: ErrorContext,
+) -> Result<tonic::Response<TonicResponseStream<O>>, tonic::Status> {
+    let stream = result_future.await.map_err(|e| error_context.map(e))?;
+    Ok(tonic::Response::new(Box::pin(stream.map(move |item| {
+        Ok(item.map_err(|e| error_context.map(e))?)
+    }))))
+}
+
 #[cfg(feature = "grpc")]
 type TonicResponseFuture<R> =
     Pin<Box<dyn Future<Output = Result<tonic::Response<R>, tonic::Status>> + Send + 'static>>;
@@ -426,11 +569,18 @@ fn grpc_to_twirp_request<T>(request: tonic::Request<T>) -> (T, RequestParts) {
     (request, parts)
 }
 
+#[cfg(feature = "grpc")]
+type ItemCheck<O> = Arc<dyn Fn(&O) -> Result<(), TwirpError> + Send + Sync>;
+
 #[cfg(feature = "grpc")]
 pin_project! {
+    /// Stream of the request items of a gRPC client streaming method
+    ///
+    /// With the `validate` feature, each item is validated when it is read and an invalid one is returned as an error.
     pub struct GrpcClientStream<O> {
         #[pin]
         stream: tonic::Streaming<O>,
+        check: Option<ItemCheck<O>>,
     }
 }
 
@@ -442,11 +592,17 @@ impl<O> Stream for GrpcClientStream<O> {
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<O, TwirpError>>> {
-        self.as_mut()
-            .project()
-            .stream
-            .poll_next(cx)
-            .map(|opt| opt.map(|r| Ok(r?)))
+        let this = self.as_mut().project();
+        let check = this.check;
+        this.stream.poll_next(cx).map(|opt| {
+            opt.map(|r| {
+                let item = r?;
+                if let Some(check) = check {
+                    check(&item)?;
+                }
+                Ok(item)
+            })
+        })
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -475,7 +631,7 @@ pub async fn twirp_error_from_response(response: impl IntoResponse) -> TwirpErro
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::twirp_fallback;
+    use crate::{TwirpErrorMapper, twirp_fallback, twirp_fallback_with_extensions};
     #[cfg(feature = "grpc")]
     use axum::http::uri::PathAndQuery;
     use axum::http::{Method, Request, StatusCode};
@@ -519,6 +675,25 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_bad_route_with_extensions() {
+        let router = TwirpRouter::new(())
+            .build()
+            .fallback(twirp_fallback_with_extensions);
+        let mut request = Request::new(Body::empty());
+        request
+            .extensions_mut()
+            .insert(TwirpErrorMapper::new(|error| {
+                TwirpError::new(error.code(), "Not supported")
+            }));
+        let response = router.into_service().call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            b"{\"code\":\"bad_route\",\"msg\":\"Not supported\"}".as_slice()
+        );
+    }
+
     #[tokio::test]
     async fn test_no_content_type() {
         let router = TwirpRouter::new(())
@@ -686,6 +861,52 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "validate")]
+    static VALIDATED_DESCRIPTOR_POOL: std::sync::LazyLock<prost_reflect::DescriptorPool> =
+        std::sync::LazyLock::new(crate::validate::tests::pool);
+
+    /// `city` must not be empty
+    #[cfg(feature = "validate")]
+    #[derive(Message, ReflectMessage, PartialEq)]
+    #[prost_reflect(
+        descriptor_pool = "VALIDATED_DESCRIPTOR_POOL",
+        message_name = "test.Address"
+    )]
+    pub struct Address {
+        #[prost(string, tag = "1")]
+        city: String,
+    }
+
+    #[cfg(feature = "validate")]
+    #[tokio::test]
+    async fn test_invalid_request() {
+        let router = TwirpRouter::new(())
+            .route(
+                "/test.MyService/MyMethod",
+                |(), _: Address, _, _| async move {
+                    Err::<Address, _>(TwirpError::internal("must not be called"))
+                },
+            )
+            .build();
+        let response = router
+            .into_service()
+            .call(
+                Request::builder()
+                    .method(Method::POST)
+                    .header(CONTENT_TYPE, APPLICATION_JSON)
+                    .uri("/test.MyService/MyMethod")
+                    .body(Body::from(b"{\"city\": \"\"}".to_vec()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            b"{\"code\":\"invalid_argument\",\"msg\":\"Invalid request: city: value length must be at least 1 characters\",\"meta\":{\"city\":\"value length must be at least 1 characters\"}}".as_slice()
+        );
+    }
+
     #[cfg(feature = "grpc")]
     #[tokio::test]
     async fn test_grpc_request() {
@@ -731,4 +952,66 @@ mod tests {
         assert_eq!(status.code(), Code::NotFound);
         assert_eq!(status.message(), "foo not found");
     }
+
+    #[cfg(all(feature = "grpc", feature = "validate"))]
+    #[tokio::test]
+    async fn test_grpc_invalid_request() {
+        let router = GrpcRouter::new(())
+            .route("/test.MyService/MyMethod", |(), _: Address, _| async move {
+                Err::<Address, _>(TwirpError::internal("must not be called"))
+            })
+            .build();
+        let path = PathAndQuery::from_static("/test.MyService/MyMethod");
+        let status = Grpc::new(router)
+            .unary::<_, Address, _>(
+                tonic::Request::new(Address::default()),
+                path,
+                ProstCodec::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "Invalid request: city: value length must be at least 1 characters"
+        );
+    }
+
+    #[cfg(all(feature = "grpc", feature = "validate"))]
+    #[tokio::test]
+    async fn test_grpc_invalid_client_streaming_item() {
+        let router = GrpcRouter::new(())
+            .route_client_streaming(
+                "/test.MyService/MyMethod",
+                |(), mut requests: GrpcClientStream<Address>, _| async move {
+                    let mut count = 0;
+                    while let Some(request) = requests.next().await {
+                        request?;
+                        count += 1;
+                    }
+                    Err::<Address, _>(TwirpError::internal(format!("{count} items read")))
+                },
+            )
+            .build();
+        let path = PathAndQuery::from_static("/test.MyService/MyMethod");
+        let requests = tokio_stream::iter([
+            Address {
+                city: "Berlin".into(),
+            },
+            Address::default(),
+        ]);
+        let status = Grpc::new(router)
+            .client_streaming::<_, Address, Address, _>(
+                tonic::Request::new(requests),
+                path,
+                ProstCodec::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "Invalid request: city: value length must be at least 1 characters"
+        );
+    }
 }
@@ -0,0 +1,154 @@
+use crate::request_id::{self, RequestId, random_id};
+use crate::{TwirpError, TwirpErrorCode};
+use axum::http::Extensions;
+use std::error::Error;
+use std::fmt::Write;
+use std::sync::Arc;
+use tracing::error;
+
+/// Rewrites the [`TwirpError`]s before they are sent to the clients.
+///
+/// It is applied both to the errors returned by the service methods and extractors
+/// and to the errors generated by `twurst-server` itself (e.g. request decoding errors).
+/// It is read from the request extensions, e.g. set with an [`Extension`](axum::Extension) layer.
+/// Note that axum layers only apply to the routes and fallbacks already added to the router.
+///
+/// ```
+/// use axum::{Extension, Router};
+/// use twurst_server::{TwirpError, TwirpErrorCode, TwirpErrorMapper};
+///
+/// let _router = Router::<()>::new().layer(Extension(TwirpErrorMapper::new(|error| {
+///     if error.code() == TwirpErrorCode::NotFound {
+///         TwirpError::not_found("Not found")
+///     } else {
+///         error
+///     }
+/// })));
+/// ```
+#[derive(Clone)]
+pub struct TwirpErrorMapper {
+    mapper: Arc<dyn Fn(TwirpError) -> TwirpError + Send + Sync>,
+}
+
+impl TwirpErrorMapper {
+    /// Mapper applying the given function to every error sent to the clients
+    pub fn new(mapper: impl Fn(TwirpError) -> TwirpError + Send + Sync + 'static) -> Self {
+        Self {
+            mapper: Arc::new(mapper),
+        }
+    }
+
+    /// Mapper replacing the message of the `internal`, `unknown` and `dataloss` errors with a generic one.
+    ///
+    /// A correlation ID is returned in the `correlation_id` meta
+    /// and logged alongside the original error and its [`source`](Error::source) chain.
+    /// It is the request ID if the [`request_id::middleware`] is used, a random one otherwise.
+    pub fn redact_internal_errors() -> Self {
+        Self::new(|error| {
+            let code = error.code();
+            if !matches!(
+                code,
+                TwirpErrorCode::Internal | TwirpErrorCode::Unknown | TwirpErrorCode::Dataloss
+            ) {
+                return error;
+            }
+            let correlation_id = request_id::current().unwrap_or_else(random_id);
+            error!(correlation_id, "{}", error_chain(&error));
+            TwirpError::new(
+                code,
+                format!("Internal error, correlation ID: {correlation_id}"),
+            )
+            .with_meta("correlation_id", correlation_id)
+        })
+    }
+
+    /// Applies the mapper to the error
+    pub fn map(&self, error: TwirpError) -> TwirpError {
+        (self.mapper)(error)
+    }
+}
+
+/// Post-processing of the errors returned to the clients
+#[derive(Clone, Default)]
+pub(crate) struct ErrorContext {
+    mapper: Option<TwirpErrorMapper>,
+    request_id: Option<RequestId>,
+}
+
+impl ErrorContext {
+    pub(crate) fn from_extensions(extensions: &Extensions) -> Self {
+        Self {
+            mapper: extensions.get().cloned(),
+            request_id: extensions.get().cloned(),
+        }
+    }
+
+    /// Applies the error mapper and adds the request ID to the error meta
+    pub(crate) fn map(&self, error: TwirpError) -> TwirpError {
+        let error = match &self.mapper {
+            Some(mapper) => mapper.map(error),
+            None => error,
+        };
+        match &self.request_id {
+            Some(request_id) if error.meta("request_id").is_none() => {
+                error.with_meta("request_id", request_id.as_str())
+            }
+            _ => error,
+        }
+    }
+}
+
+fn error_chain(error: &TwirpError) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        let _ = write!(chain, ": {error}");
+        source = error.source();
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn redact_internal_errors() {
+        let mapper = TwirpErrorMapper::redact_internal_errors();
+        let error = mapper.map(TwirpError::wrap(
+            TwirpErrorCode::Internal,
+            "Failed to connect to the database at 10.0.0.1",
+            io::Error::other("connection refused"),
+        ));
+        assert_eq!(error.code(), TwirpErrorCode::Internal);
+        let correlation_id = error.meta("correlation_id").unwrap();
+        assert_eq!(
+            error.message(),
+            format!("Internal error, correlation ID: {correlation_id}")
+        );
+        assert_ne!(
+            correlation_id,
+            mapper
+                .map(TwirpError::internal("foo"))
+                .meta("correlation_id")
+                .unwrap()
+        );
+
+        let error = TwirpError::invalid_argument("Bad name");
+        assert_eq!(mapper.map(error.clone()), error);
+    }
+
+    #[test]
+    fn error_chain_includes_sources() {
+        let error = TwirpError::wrap(
+            TwirpErrorCode::Internal,
+            "Failed to load",
+            io::Error::other("connection refused"),
+        );
+        assert_eq!(
+            error_chain(&error),
+            "Twirp Internal error: Failed to load: connection refused"
+        );
+    }
+}
@@ -8,13 +8,37 @@
 
 #[doc(hidden)]
 pub mod codegen;
+mod error_mapper;
+#[cfg(feature = "playground")]
+pub mod playground;
+mod redact;
+pub mod request_id;
+#[cfg(feature = "validate")]
+mod validate;
 
-use axum::http::Uri;
+use axum::http::{Extensions, Uri};
 use axum::response::IntoResponse;
+pub use error_mapper::TwirpErrorMapper;
+pub use redact::{Redacted, redacted};
+pub use request_id::RequestId;
+pub use twurst_error::any::{AnyResolver, TypeUrlResolver, UnknownAny};
+pub use twurst_error::registry::DescriptorRegistry;
 pub use twurst_error::{TwirpError, TwirpErrorCode};
 
 /// Fallback method to be used with a Twirp router
+///
+/// Use [`twirp_fallback_with_extensions`] to apply the [`TwirpErrorMapper`] and the request ID to the returned error.
 pub async fn twirp_fallback(uri: Uri) -> impl IntoResponse {
+    bad_route(&uri)
+}
+
+/// Like [`twirp_fallback`] but the returned error is rewritten by the [`TwirpErrorMapper`]
+/// and gets the `request_id` meta from the request extensions
+pub async fn twirp_fallback_with_extensions(uri: Uri, extensions: Extensions) -> impl IntoResponse {
+    error_mapper::ErrorContext::from_extensions(&extensions).map(bad_route(&uri))
+}
+
+fn bad_route(uri: &Uri) -> TwirpError {
     TwirpError::new(
         TwirpErrorCode::BadRoute,
         format!("{} is not a supported Twirp method", uri.path()),
@@ -0,0 +1,402 @@
+//! Web playground to explore and call the Twirp services of a [`DescriptorPool`].
+//!
+//! ```
+//! use axum::Router;
+//! use prost_reflect::DescriptorPool;
+//! use twurst_server::playground::Playground;
+//!
+//! # let pool = DescriptorPool::global();
+//! let _router = Router::<()>::new()
+//!     // .nest("/twirp", ExampleServiceServicer {}.into_router())
+//!     .nest("/playground", Playground::new(pool).twirp_prefix("/twirp").into_router());
+//! ```
+
+use axum::Router;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::Html;
+use axum::routing::get;
+use prost_reflect::{DescriptorPool, FieldDescriptor, FileDescriptor, Kind, MessageDescriptor};
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+
+const PAGE: &str = include_str!("playground.html");
+
+/// Maximal depth of nested messages in the generated example requests
+const MAX_EXAMPLE_DEPTH: usize = 3;
+
+/// Router serving an HTML page listing the services, methods and messages of a [`DescriptorPool`],
+/// with their comments, and a form to send JSON requests to the Twirp routes.
+///
+/// The schema is also served as JSON on `/schema.json`.
+/// The descriptors of the generated code are available with `ReflectMessage::descriptor().parent_pool()`.
+#[derive(Clone, Debug)]
+pub struct Playground {
+    pool: DescriptorPool,
+    twirp_prefix: String,
+}
+
+impl Playground {
+    /// Playground of all the services of the pool, calling the Twirp routes at the root path by default
+    pub fn new(pool: DescriptorPool) -> Self {
+        Self {
+            pool,
+            twirp_prefix: String::new(),
+        }
+    }
+
+    /// Path under which the Twirp routes are nested, e.g. `/twirp`. Empty by default.
+    pub fn twirp_prefix(mut self, twirp_prefix: impl Into<String>) -> Self {
+        self.twirp_prefix = twirp_prefix.into();
+        self
+    }
+
+    /// JSON description of the services and of the messages they use
+    pub fn schema(&self) -> Value {
+        let mut services = Vec::new();
+        let mut messages = BTreeSet::new();
+        for service in self.pool.services() {
+            let methods = service
+                .methods()
+                .map(|method| {
+                    collect_messages(&method.input(), &mut messages);
+                    collect_messages(&method.output(), &mut messages);
+                    json!({
+                        "name": method.name(),
+                        "path": format!("/{}/{}", service.full_name(), method.name()),
+                        "comments": comments(&method.parent_file(), method.path()),
+                        "input": method.input().full_name(),
+                        "output": method.output().full_name(),
+                        "clientStreaming": method.is_client_streaming(),
+                        "serverStreaming": method.is_server_streaming(),
+                        "example": example(&method.input(), 0),
+                    })
+                })
+                .collect::<Vec<_>>();
+            services.push(json!({
+                "name": service.full_name(),
+                "comments": comments(&service.parent_file(), service.path()),
+                "methods": methods,
+            }));
+        }
+        let messages = messages
+            .into_iter()
+            .filter_map(|name| self.pool.get_message_by_name(&name))
+            .map(|message| {
+                let description = json!({
+                    "comments": comments(&message.parent_file(), message.path()),
+                    "fields": message.fields().map(|field| describe_field(&field)).collect::<Vec<_>>(),
+                });
+                (message.full_name().to_owned(), description)
+            })
+            .collect::<BTreeMap<_, _>>();
+        json!({
+            "twirpPrefix": self.twirp_prefix,
+            "services": services,
+            "messages": messages,
+        })
+    }
+
+    /// Router serving the page on `/` and the schema on `/schema.json`
+    pub fn into_router<S: Clone + Send + Sync + 'static>(self) -> Router<S> {
+        let schema = self.schema().to_string();
+        // The schema is embedded in a <script> element, it must not close it
+        let page = PAGE.replace("{{SCHEMA}}", &schema.replace("</", "<\\/"));
+        Router::new()
+            .route("/", get(move || async move { Html(page.clone()) }))
+            .route(
+                "/schema.json",
+                get(move || async move { ([(CONTENT_TYPE, "application/json")], schema.clone()) }),
+            )
+    }
+}
+
+/// Adds the message and all the messages it references, except the well-known types
+fn collect_messages(message: &MessageDescriptor, messages: &mut BTreeSet<String>) {
+    if message.package_name() == "google.protobuf"
+        || !messages.insert(message.full_name().to_owned())
+    {
+        return;
+    }
+    for field in message.fields() {
+        if let Some(field_message) = value_kind(&field).as_message() {
+            collect_messages(field_message, messages);
+        }
+    }
+}
+
+fn describe_field(field: &FieldDescriptor) -> Value {
+    let label = if field.is_map() {
+        "map"
+    } else if field.is_list() {
+        "repeated"
+    } else {
+        "single"
+    };
+    let mut description = json!({
+        "name": field.name(),
+        "jsonName": field.json_name(),
+        "type": kind_name(&value_kind(field)),
+        "label": label,
+        "comments": comments(&field.parent_file(), field.path()),
+    });
+    if let Some(entry) = field.kind().as_message().filter(|_| field.is_map()) {
+        description["keyType"] = kind_name(&entry.map_entry_key_field().kind()).into();
+    }
+    if let Some(oneof) = field.containing_oneof().filter(|o| !o.is_synthetic()) {
+        description["oneof"] = oneof.name().into();
+    }
+    description
+}
+
+/// The kind of the values of the field, i.e. of the map values for maps
+fn value_kind(field: &FieldDescriptor) -> Kind {
+    match field.kind() {
+        Kind::Message(entry) if field.is_map() => entry.map_entry_value_field().kind(),
+        kind => kind,
+    }
+}
+
+fn kind_name(kind: &Kind) -> String {
+    match kind {
+        Kind::Double => "double".into(),
+        Kind::Float => "float".into(),
+        Kind::Int32 => "int32".into(),
+        Kind::Int64 => "int64".into(),
+        Kind::Uint32 => "uint32".into(),
+        Kind::Uint64 => "uint64".into(),
+        Kind::Sint32 => "sint32".into(),
+        Kind::Sint64 => "sint64".into(),
+        Kind::Fixed32 => "fixed32".into(),
+        Kind::Fixed64 => "fixed64".into(),
+        Kind::Sfixed32 => "sfixed32".into(),
+        Kind::Sfixed64 => "sfixed64".into(),
+        Kind::Bool => "bool".into(),
+        Kind::String => "string".into(),
+        Kind::Bytes => "bytes".into(),
+        Kind::Message(message) => message.full_name().into(),
+        Kind::Enum(enumeration) => enumeration.full_name().into(),
+    }
+}
+
+/// Example JSON value of a message following the protobuf JSON mapping
+fn example(message: &MessageDescriptor, depth: usize) -> Value {
+    match message.full_name() {
+        "google.protobuf.Timestamp" => return "1970-01-01T00:00:00Z".into(),
+        "google.protobuf.Duration" => return "0s".into(),
+        "google.protobuf.FieldMask" => return "".into(),
+        "google.protobuf.Value" => return Value::Null,
+        "google.protobuf.ListValue" => return json!([]),
+        "google.protobuf.Any" => return json!({"@type": ""}),
+        "google.protobuf.BoolValue"
+        | "google.protobuf.BytesValue"
+        | "google.protobuf.DoubleValue"
+        | "google.protobuf.FloatValue"
+        | "google.protobuf.Int32Value"
+        | "google.protobuf.Int64Value"
+        | "google.protobuf.StringValue"
+        | "google.protobuf.UInt32Value"
+        | "google.protobuf.UInt64Value" => {
+            if let Some(field) = message.get_field(1) {
+                return example_value(&field.kind(), depth);
+            }
+        }
+        _ => (),
+    }
+    if depth >= MAX_EXAMPLE_DEPTH {
+        return json!({});
+    }
+    let mut object = Map::new();
+    for field in message.fields() {
+        // Only the first field of each oneof is set
+        if let Some(oneof) = field.containing_oneof()
+            && !oneof.is_synthetic()
+            && oneof.fields().next().is_some_and(|first| first != field)
+        {
+            continue;
+        }
+        let value = if field.is_map() {
+            json!({})
+        } else if field.is_list() {
+            json!([])
+        } else {
+            example_value(&field.kind(), depth + 1)
+        };
+        object.insert(field.json_name().into(), value);
+    }
+    object.into()
+}
+
+fn example_value(kind: &Kind, depth: usize) -> Value {
+    match kind {
+        Kind::Double | Kind::Float => 0.0.into(),
+        Kind::Int32 | Kind::Uint32 | Kind::Sint32 | Kind::Fixed32 | Kind::Sfixed32 => 0.into(),
+        // 64 bits integers are encoded as strings
+        Kind::Int64 | Kind::Uint64 | Kind::Sint64 | Kind::Fixed64 | Kind::Sfixed64 => "0".into(),
+        Kind::Bool => false.into(),
+        Kind::String | Kind::Bytes => "".into(),
+        Kind::Message(message) => example(message, depth),
+        Kind::Enum(enumeration) => enumeration
+            .values()
+            .next()
+            .map_or(Value::Null, |value| value.name().into()),
+    }
+}
+
+/// Leading comments of the element at the given path
+fn comments(file: &FileDescriptor, path: &[i32]) -> String {
+    file.file_descriptor_proto()
+        .source_code_info
+        .as_ref()
+        .and_then(|info| info.location.iter().find(|location| location.path == path))
+        .and_then(|location| location.leading_comments.as_deref())
+        .map_or_else(String::new, |comments| comments.trim().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+    use tower_service::Service;
+
+    const TEST_PROTO: &str = r#"
+        syntax = "proto3";
+        package test;
+        import "google/protobuf/timestamp.proto";
+
+        // Manages users
+        service UserService {
+          // Creates a user
+          rpc CreateUser(CreateUserRequest) returns (User);
+          rpc WatchUsers(CreateUserRequest) returns (stream User);
+        }
+
+        message CreateUserRequest {
+          // The user name, e.g. </script>
+          string name = 1;
+          Role role = 2;
+          map<string, Address> addresses = 3;
+          oneof contact {
+            string phone = 4;
+            string email = 5;
+          }
+          google.protobuf.Timestamp birth = 6;
+          int64 id = 7;
+        }
+
+        message User {
+          string name = 1;
+        }
+
+        message Address {
+          string city = 1;
+        }
+
+        enum Role {
+          ROLE_UNSPECIFIED = 0;
+          ROLE_ADMIN = 1;
+        }
+    "#;
+
+    struct TestFileResolver;
+
+    impl FileResolver for TestFileResolver {
+        fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+            match name {
+                "test.proto" => File::from_source(name, TEST_PROTO),
+                _ => Err(protox::Error::file_not_found(name)),
+            }
+        }
+    }
+
+    fn pool() -> DescriptorPool {
+        let mut resolver = ChainFileResolver::new();
+        resolver.add(GoogleFileResolver::new());
+        resolver.add(TestFileResolver);
+        let mut compiler = protox::Compiler::with_file_resolver(resolver);
+        compiler
+            .include_source_info(true)
+            .open_file("test.proto")
+            .unwrap();
+        compiler.descriptor_pool()
+    }
+
+    #[test]
+    fn schema() {
+        let schema = Playground::new(pool()).twirp_prefix("/twirp").schema();
+        assert_eq!(schema["twirpPrefix"], "/twirp");
+        let service = &schema["services"][0];
+        assert_eq!(service["name"], "test.UserService");
+        assert_eq!(service["comments"], "Manages users");
+        assert_eq!(
+            service["methods"][0],
+            json!({
+                "name": "CreateUser",
+                "path": "/test.UserService/CreateUser",
+                "comments": "Creates a user",
+                "input": "test.CreateUserRequest",
+                "output": "test.User",
+                "clientStreaming": false,
+                "serverStreaming": false,
+                "example": {
+                    "name": "",
+                    "role": "ROLE_UNSPECIFIED",
+                    "addresses": {},
+                    "phone": "",
+                    "birth": "1970-01-01T00:00:00Z",
+                    "id": "0"
+                }
+            })
+        );
+        assert_eq!(service["methods"][1]["serverStreaming"], true);
+        assert_eq!(
+            schema["messages"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            ["test.Address", "test.CreateUserRequest", "test.User"]
+        );
+        assert_eq!(
+            schema["messages"]["test.CreateUserRequest"]["fields"][2],
+            json!({
+                "name": "addresses",
+                "jsonName": "addresses",
+                "type": "test.Address",
+                "keyType": "string",
+                "label": "map",
+                "comments": ""
+            })
+        );
+        assert_eq!(
+            schema["messages"]["test.CreateUserRequest"]["fields"][4]["oneof"],
+            "contact"
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_page_and_schema() {
+        let mut router = Playground::new(pool()).into_router::<()>();
+        let response = router
+            .call(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = response.into_body().collect().await.unwrap().to_bytes();
+        let page = std::str::from_utf8(&page).unwrap();
+        assert!(page.contains("\"test.UserService\""));
+        assert!(page.contains("e.g. <\\/script>"));
+
+        let response = router
+            .call(Request::get("/schema.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let schema: Value =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(schema["services"][0]["methods"][0]["name"], "CreateUser");
+    }
+}
@@ -0,0 +1,166 @@
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, MapKey, ReflectMessage, Value};
+use std::fmt;
+
+/// Formats a message for logs with the fields annotated with `debug_redact = true` replaced by `[REDACTED]`.
+///
+/// Unlike the `Debug` implementations of the messages, it works with [`DynamicMessage`]
+/// and only prints the fields that are set.
+///
+/// ```
+/// use prost_reflect::{DynamicMessage, ReflectMessage};
+/// use twurst_server::redacted;
+///
+/// fn log_request<M: ReflectMessage>(request: &M) {
+///     tracing::info!(request = ?redacted(request), "Received request");
+/// }
+/// # let _ = log_request::<DynamicMessage>;
+/// ```
+pub fn redacted<M: ReflectMessage>(message: &M) -> Redacted {
+    Redacted(message.transcode_to_dynamic())
+}
+
+/// Message wrapper returned by [`redacted`]
+pub struct Redacted(DynamicMessage);
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_message(&self.0, f)
+    }
+}
+
+/// Whether the field is annotated with `debug_redact = true`
+fn is_redacted(field: &FieldDescriptor) -> bool {
+    field
+        .options()
+        .get_field_by_name("debug_redact")
+        .is_some_and(|value| matches!(*value, Value::Bool(true)))
+}
+
+fn fmt_message(message: &DynamicMessage, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut debug = f.debug_struct(message.descriptor().name());
+    for (field, value) in message.fields() {
+        if is_redacted(&field) {
+            debug.field(field.name(), &format_args!("[REDACTED]"));
+        } else {
+            debug.field(field.name(), &RedactedValue(value, field.kind()));
+        }
+    }
+    debug.finish()
+}
+
+struct RedactedValue<'a>(&'a Value, Kind);
+
+impl fmt::Debug for RedactedValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Value::Bool(value) => value.fmt(f),
+            Value::I32(value) => value.fmt(f),
+            Value::I64(value) => value.fmt(f),
+            Value::U32(value) => value.fmt(f),
+            Value::U64(value) => value.fmt(f),
+            Value::F32(value) => value.fmt(f),
+            Value::F64(value) => value.fmt(f),
+            Value::String(value) => value.fmt(f),
+            Value::Bytes(value) => value[..].fmt(f),
+            Value::EnumNumber(number) => {
+                match self.1.as_enum().and_then(|e| e.get_value(*number)) {
+                    Some(value) => f.write_str(value.name()),
+                    None => number.fmt(f),
+                }
+            }
+            Value::Message(message) => fmt_message(message, f),
+            Value::List(values) => f
+                .debug_list()
+                .entries(
+                    values
+                        .iter()
+                        .map(|value| RedactedValue(value, self.1.clone())),
+                )
+                .finish(),
+            Value::Map(entries) => {
+                let Some(entry) = self.1.as_message() else {
+                    return f.write_str("{..}");
+                };
+                let value_kind = entry.map_entry_value_field().kind();
+                f.debug_map()
+                    .entries(entries.iter().map(|(key, value)| {
+                        (RedactedKey(key), RedactedValue(value, value_kind.clone()))
+                    }))
+                    .finish()
+            }
+        }
+    }
+}
+
+struct RedactedKey<'a>(&'a MapKey);
+
+impl fmt::Debug for RedactedKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            MapKey::Bool(key) => key.fmt(f),
+            MapKey::I32(key) => key.fmt(f),
+            MapKey::I64(key) => key.fmt(f),
+            MapKey::U32(key) => key.fmt(f),
+            MapKey::U64(key) => key.fmt(f),
+            MapKey::String(key) => key.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::DescriptorPool;
+    use protox::file::{File, FileResolver};
+
+    const TEST_PROTO: &str = r#"
+        syntax = "proto3";
+        package test;
+
+        message Login {
+          string user = 1;
+          string password = 2 [debug_redact = true];
+          Role role = 3;
+          map<string, Login> delegates = 4;
+          oneof token {
+            bytes api_key = 5 [debug_redact = true];
+            uint64 session = 6;
+          }
+        }
+
+        enum Role {
+          ROLE_UNSPECIFIED = 0;
+          ROLE_ADMIN = 1;
+        }
+    "#;
+
+    struct TestFileResolver;
+
+    impl FileResolver for TestFileResolver {
+        fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+            match name {
+                "test.proto" => File::from_source(name, TEST_PROTO),
+                _ => Err(protox::Error::file_not_found(name)),
+            }
+        }
+    }
+
+    fn pool() -> DescriptorPool {
+        let mut compiler = protox::Compiler::with_file_resolver(TestFileResolver);
+        compiler.open_file("test.proto").unwrap();
+        compiler.descriptor_pool()
+    }
+
+    #[test]
+    fn redacts_annotated_fields() {
+        let descriptor = pool().get_message_by_name("test.Login").unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(
+            r#"{"user": "alice", "password": "secret", "role": "ROLE_ADMIN", "apiKey": "c2VjcmV0", "delegates": {"bob": {"user": "bob", "password": "secret", "session": 1}}}"#,
+        );
+        let message = DynamicMessage::deserialize(descriptor, &mut deserializer).unwrap();
+        assert_eq!(
+            format!("{:?}", redacted(&message)),
+            r#"Login { user: "alice", password: [REDACTED], role: ROLE_ADMIN, delegates: {"bob": Login { user: "bob", password: [REDACTED], session: 1 }}, api_key: [REDACTED] }"#
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Request ID handling.
+//!
+//! The [`middleware`] reads the request ID from the incoming `x-request-id` header or generates one.
+//! It is then:
+//! - available to the service methods with the [`RequestId`] extractor,
+//! - echoed in the `x-request-id` response header,
+//! - added to the `request_id` meta of the returned Twirp errors,
+//! - recorded in a `request` [`tracing`] span,
+//! - forwarded by `twurst-client` in the calls made while processing the request.
+//!
+//! The forwarding relies on [`scope`]: the calls made from spawned tasks
+//! or while the stream of a gRPC server streaming response is polled don't get the request ID.
+//!
+//! ```
+//! use axum::Router;
+//! use axum::middleware::from_fn;
+//! use twurst_server::request_id;
+//!
+//! let _router = Router::<()>::new().layer(from_fn(request_id::middleware));
+//! ```
+
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::convert::Infallible;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{Instrument, error, info_span};
+pub use twurst_error::request_id::{REQUEST_ID_HEADER, current, scope};
+
+/// Maximal length of the incoming request IDs, longer ones are replaced by a generated one
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The ID of the request being processed.
+///
+/// Set by the [`middleware`]. If the middleware is not used, it is read from the `x-request-id` header
+/// or a new one is generated and kept in the request extensions for the next extractors.
+/// Such an ID is only known to the service method: it is not echoed in the response header,
+/// not added to the error meta and not forwarded by `twurst-client`, use the [`middleware`] for that.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LEN)
+            .map_or_else(|| Self(random_id()), |value| Self(value.into()))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Infallible> {
+        if let Some(request_id) = parts.extensions.get::<Self>() {
+            return Ok(request_id.clone());
+        }
+        let request_id = Self::from_headers(&parts.headers);
+        parts.extensions.insert(request_id.clone());
+        Ok(request_id)
+    }
+}
+
+/// Axum middleware setting up the request ID, to be used with [`from_fn`](axum::middleware::from_fn)
+pub async fn middleware(mut request: Request, next: Next) -> Response {
+    let request_id = RequestId::from_headers(request.headers());
+    request.extensions_mut().insert(request_id.clone());
+    let span = info_span!("request", request_id = request_id.as_str());
+    let mut response = scope(request_id.as_str(), next.run(request))
+        .instrument(span)
+        .await;
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Random 128 bits identifier encoded in hexadecimal, from the operating system random number generator
+pub(crate) fn random_id() -> String {
+    let mut bytes = [0; 16];
+    if let Err(e) = getrandom::fill(&mut bytes) {
+        // Unique within the process only
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        error!("Failed to generate a random identifier, falling back to a counter: {e}");
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        bytes[..8].copy_from_slice(&nanos.to_be_bytes());
+        bytes[8..].copy_from_slice(&COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    }
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn generated_request_id_is_kept() {
+        let (mut parts, ()) = axum::http::Request::new(()).into_parts();
+        let first = RequestId::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        let second = RequestId::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,1265 @@
+//! Request validation based on the [`buf.validate`](https://buf.build/bufbuild/protovalidate) annotations.
+//!
+//! Only a subset of the standard rules is supported, see the README for the list.
+//! The other rules, including CEL expressions, are logged as errors when the validator is built and then ignored.
+//!
+//! Requests are validated right after being decoded, before the axum extractors of the method run.
+//! A caller rejected by an extractor, e.g. an authentication one, still gets the field errors of an invalid request.
+
+use crate::TwirpError;
+use prost_reflect::{
+    DynamicMessage, ExtensionDescriptor, FieldDescriptor, Kind, MessageDescriptor, OneofDescriptor,
+    ReflectMessage, Value, prost_types,
+};
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+const IGNORE_IF_ZERO_VALUE: i32 = 1;
+const IGNORE_ALWAYS: i32 = 3;
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+const WRAPPER_TYPES: &[&str] = &[
+    "google.protobuf.DoubleValue",
+    "google.protobuf.FloatValue",
+    "google.protobuf.Int64Value",
+    "google.protobuf.UInt64Value",
+    "google.protobuf.Int32Value",
+    "google.protobuf.UInt32Value",
+    "google.protobuf.BoolValue",
+    "google.protobuf.StringValue",
+    "google.protobuf.BytesValue",
+];
+
+/// Validates the messages of a given type against their `buf.validate` rules
+pub(crate) struct Validator {
+    // rules of all the messages reachable from the validated message, by full name
+    messages: HashMap<String, MessageRules>,
+    // compiled string patterns
+    patterns: HashMap<String, Regex>,
+}
+
+#[derive(Default)]
+struct MessageRules {
+    // fields with rules or that might contain messages with rules
+    fields: Vec<(FieldDescriptor, Option<DynamicMessage>)>,
+    required_oneofs: Vec<OneofDescriptor>,
+}
+
+struct Violation {
+    path: String,
+    message: String,
+}
+
+impl Validator {
+    /// Builds the validator of a message type, returns `None` if there are no rules to check
+    pub(crate) fn new(descriptor: &MessageDescriptor) -> Option<Self> {
+        let pool = descriptor.parent_pool();
+        let field_extension = pool.get_extension_by_name("buf.validate.field")?;
+        let oneof_extension = pool.get_extension_by_name("buf.validate.oneof");
+        let mut validator = Self {
+            messages: HashMap::new(),
+            patterns: HashMap::new(),
+        };
+        let mut has_rules = false;
+        let mut to_visit = vec![descriptor.clone()];
+        while let Some(message) = to_visit.pop() {
+            if validator.messages.contains_key(message.full_name()) {
+                continue;
+            }
+            let mut rules = MessageRules::default();
+            for field in message.fields() {
+                let field_rules = extension_message(&field.options(), &field_extension);
+                if let Some(field_rules) = &field_rules {
+                    for unsupported in unsupported_rules(field_rules) {
+                        error!(
+                            "Unsupported buf.validate rule {unsupported} on field {}, it is not checked",
+                            field.full_name()
+                        );
+                    }
+                    validator.add_patterns(field_rules);
+                    has_rules = true;
+                }
+                let kind = if field.is_map() {
+                    field
+                        .kind()
+                        .as_message()
+                        .map(|entry| entry.map_entry_value_field().kind())
+                } else {
+                    Some(field.kind())
+                };
+                let is_message = if let Some(Kind::Message(nested)) = kind {
+                    to_visit.push(nested);
+                    true
+                } else {
+                    false
+                };
+                if field_rules.is_some() || is_message {
+                    rules.fields.push((field, field_rules));
+                }
+            }
+            if let Some(oneof_extension) = &oneof_extension {
+                for oneof in message.oneofs().filter(|o| !o.is_synthetic()) {
+                    if extension_message(&oneof.options(), oneof_extension)
+                        .is_some_and(|r| rule_is_true(&r, "required"))
+                    {
+                        rules.required_oneofs.push(oneof);
+                        has_rules = true;
+                    }
+                }
+            }
+            validator
+                .messages
+                .insert(message.full_name().to_owned(), rules);
+        }
+        has_rules.then_some(validator)
+    }
+
+    fn add_patterns(&mut self, field_rules: &DynamicMessage) {
+        for kind in ["string", "bytes"] {
+            if let Some(pattern) = rule_message(field_rules, kind)
+                .and_then(|r| rule(r, "pattern"))
+                .and_then(|p| p.as_str().map(ToOwned::to_owned))
+            {
+                match Regex::new(&pattern) {
+                    Ok(regex) => {
+                        self.patterns.insert(pattern, regex);
+                    }
+                    Err(e) => error!("Invalid buf.validate pattern {pattern}: {e}"),
+                }
+            }
+        }
+        for (kind, name) in [("repeated", "items"), ("map", "keys"), ("map", "values")] {
+            if let Some(nested) =
+                rule_message(field_rules, kind).and_then(|r| rule_message(r, name))
+            {
+                self.add_patterns(nested);
+            }
+        }
+    }
+
+    /// Validates a message, returns an [`InvalidArgument`](crate::TwirpErrorCode::InvalidArgument) error
+    /// with a meta entry per violation if it is not valid
+    pub(crate) fn validate(&self, message: &impl ReflectMessage) -> Result<(), TwirpError> {
+        let mut violations = Vec::new();
+        self.validate_message(&message.transcode_to_dynamic(), "", &mut violations);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let description = violations
+            .iter()
+            .map(|v| format!("{}: {}", v.path, v.message))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(violations.into_iter().fold(
+            TwirpError::invalid_argument(format!("Invalid request: {description}")),
+            |error, v| {
+                // Several violations of the same field are joined
+                let message = match error.meta(&v.path) {
+                    Some(previous) => format!("{previous}, {}", v.message),
+                    None => v.message,
+                };
+                error.with_meta(v.path, message)
+            },
+        ))
+    }
+
+    fn validate_message(
+        &self,
+        message: &DynamicMessage,
+        path: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        let Some(rules) = self.messages.get(message.descriptor().full_name()) else {
+            return;
+        };
+        for oneof in &rules.required_oneofs {
+            if !oneof.fields().any(|f| message.has_field(&f)) {
+                violations.push(Violation::new(
+                    join(path, oneof.name()),
+                    "exactly one field is required in oneof",
+                ));
+            }
+        }
+        for (field, field_rules) in &rules.fields {
+            let field_path = join(path, field.name());
+            let is_set = message.has_field(field);
+            let value = message.get_field(field);
+            if let Some(field_rules) = field_rules {
+                self.validate_field(field, is_set, &value, field_rules, &field_path, violations);
+            }
+            if is_set {
+                self.validate_nested_messages(&value, &field_path, violations);
+            }
+        }
+    }
+
+    fn validate_nested_messages(&self, value: &Value, path: &str, violations: &mut Vec<Violation>) {
+        match value {
+            Value::Message(message) => self.validate_message(message, path, violations),
+            Value::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if let Value::Message(message) = item {
+                        self.validate_message(message, &format!("{path}[{i}]"), violations);
+                    }
+                }
+            }
+            Value::Map(entries) => {
+                for (key, value) in entries {
+                    if let Value::Message(message) = value {
+                        let key = Value::from(key.clone());
+                        self.validate_message(message, &format!("{path}[{key}]"), violations);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn validate_field(
+        &self,
+        field: &FieldDescriptor,
+        is_set: bool,
+        value: &Value,
+        rules: &DynamicMessage,
+        path: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        let ignore = rule(rules, "ignore")
+            .and_then(|v| v.as_enum_number())
+            .unwrap_or(0);
+        if ignore == IGNORE_ALWAYS {
+            return;
+        }
+        if !is_set {
+            if rule_is_true(rules, "required") {
+                violations.push(Violation::new(path, "value is required"));
+                return;
+            }
+            if ignore == IGNORE_IF_ZERO_VALUE || field.supports_presence() {
+                return;
+            }
+        }
+
+        if field.is_map() {
+            let (Some(map_rules), Value::Map(entries), Kind::Message(entry)) =
+                (rule_message(rules, "map"), value, field.kind())
+            else {
+                return;
+            };
+            check_size(
+                entries.len(),
+                map_rules,
+                "min_pairs",
+                "max_pairs",
+                "pairs",
+                path,
+                violations,
+            );
+            let key_rules = rule_message(map_rules, "keys");
+            let value_rules = rule_message(map_rules, "values");
+            for (key, value) in entries {
+                let key = Value::from(key.clone());
+                let entry_path = format!("{path}[{key}]");
+                if let Some(key_rules) = key_rules {
+                    let kind = entry.map_entry_key_field().kind();
+                    self.validate_value(&key, &kind, key_rules, &entry_path, violations);
+                }
+                if let Some(value_rules) = value_rules {
+                    let kind = entry.map_entry_value_field().kind();
+                    self.validate_value(value, &kind, value_rules, &entry_path, violations);
+                }
+            }
+        } else if field.is_list() {
+            let (Some(repeated_rules), Value::List(items)) =
+                (rule_message(rules, "repeated"), value)
+            else {
+                return;
+            };
+            check_size(
+                items.len(),
+                repeated_rules,
+                "min_items",
+                "max_items",
+                "items",
+                path,
+                violations,
+            );
+            if rule_is_true(repeated_rules, "unique")
+                && items
+                    .iter()
+                    .enumerate()
+                    .any(|(i, item)| items[..i].contains(item))
+            {
+                violations.push(Violation::new(
+                    path,
+                    "repeated value must contain unique items",
+                ));
+            }
+            if let Some(item_rules) = rule_message(repeated_rules, "items") {
+                for (i, item) in items.iter().enumerate() {
+                    let item_path = format!("{path}[{i}]");
+                    self.validate_value(item, &field.kind(), item_rules, &item_path, violations);
+                }
+            }
+        } else {
+            self.validate_value(value, &field.kind(), rules, path, violations);
+        }
+    }
+
+    /// Validates a single value against the rules specific to its type
+    fn validate_value(
+        &self,
+        value: &Value,
+        kind: &Kind,
+        rules: &DynamicMessage,
+        path: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        // Wrapper types are validated with the rules of their wrapped value
+        if let (Kind::Message(message), Value::Message(wrapper)) = (kind, value)
+            && WRAPPER_TYPES.contains(&message.full_name())
+            && let Some(field) = message.get_field_by_name("value")
+        {
+            let value = wrapper.get_field(&field);
+            return self.validate_value(&value, &field.kind(), rules, path, violations);
+        }
+        let Some(type_rules) = type_rules_name(kind).and_then(|name| rule_message(rules, name))
+        else {
+            return;
+        };
+        let mut violation = |message: String| violations.push(Violation::new(path, message));
+        match (value, kind) {
+            (Value::String(value), _) => self.validate_string(value, type_rules, &mut violation),
+            (Value::Bytes(value), _) => self.validate_bytes(value, type_rules, &mut violation),
+            (Value::Bool(value), _) => {
+                if let Some(expected) = rule(type_rules, "const").and_then(|v| v.as_bool())
+                    && *value != expected
+                {
+                    violation(format!("value must equal {expected}"));
+                }
+            }
+            (Value::EnumNumber(value), Kind::Enum(enum_descriptor)) => {
+                if rule_is_true(type_rules, "defined_only")
+                    && enum_descriptor.get_value(*value).is_none()
+                {
+                    violation("value must be one of the defined enum values".into());
+                }
+                check_const_and_lists(&Value::EnumNumber(*value), type_rules, &mut violation);
+            }
+            (Value::Message(any), Kind::Message(message))
+                if message.full_name() == "google.protobuf.Any" =>
+            {
+                validate_any(any, type_rules, &mut violation);
+            }
+            (value, _) => {
+                validate_timestamp_now(value, type_rules, &mut violation);
+                validate_number(value, type_rules, &mut violation);
+            }
+        }
+    }
+
+    fn validate_string(
+        &self,
+        value: &str,
+        rules: &DynamicMessage,
+        violation: &mut impl FnMut(String),
+    ) {
+        let chars = value.chars().count();
+        if let Some(len) = rule_u64(rules, "len")
+            && chars as u64 != len
+        {
+            violation(format!("value length must be {len} characters"));
+        }
+        if let Some(min_len) = rule_u64(rules, "min_len")
+            && (chars as u64) < min_len
+        {
+            violation(format!(
+                "value length must be at least {min_len} characters"
+            ));
+        }
+        if let Some(max_len) = rule_u64(rules, "max_len")
+            && chars as u64 > max_len
+        {
+            violation(format!("value length must be at most {max_len} characters"));
+        }
+        check_bytes_size(
+            value.len(),
+            rules,
+            "len_bytes",
+            "min_bytes",
+            "max_bytes",
+            violation,
+        );
+        if let Some(pattern) =
+            rule(rules, "pattern").and_then(|p| p.as_str().map(ToOwned::to_owned))
+            && let Some(regex) = self.patterns.get(&pattern)
+            && !regex.is_match(value)
+        {
+            violation(format!("value does not match regex pattern `{pattern}`"));
+        }
+        if let Some(prefix) = rule(rules, "prefix")
+            && let Some(prefix) = prefix.as_str()
+            && !value.starts_with(prefix)
+        {
+            violation(format!("value does not have prefix `{prefix}`"));
+        }
+        if let Some(suffix) = rule(rules, "suffix")
+            && let Some(suffix) = suffix.as_str()
+            && !value.ends_with(suffix)
+        {
+            violation(format!("value does not have suffix `{suffix}`"));
+        }
+        if let Some(contains) = rule(rules, "contains")
+            && let Some(contains) = contains.as_str()
+            && !value.contains(contains)
+        {
+            violation(format!("value does not contain substring `{contains}`"));
+        }
+        if let Some(not_contains) = rule(rules, "not_contains")
+            && let Some(not_contains) = not_contains.as_str()
+            && value.contains(not_contains)
+        {
+            violation(format!("value contains substring `{not_contains}`"));
+        }
+        let formats = [
+            (
+                "email",
+                "value must be a valid email address",
+                is_email as fn(&str) -> bool,
+            ),
+            ("hostname", "value must be a valid hostname", is_hostname),
+            ("ip", "value must be a valid IP address", |v| {
+                v.parse::<IpAddr>().is_ok()
+            }),
+            ("ipv4", "value must be a valid IPv4 address", |v| {
+                v.parse::<Ipv4Addr>().is_ok()
+            }),
+            ("ipv6", "value must be a valid IPv6 address", |v| {
+                v.parse::<Ipv6Addr>().is_ok()
+            }),
+            ("uuid", "value must be a valid UUID", is_uuid),
+            ("tuuid", "value must be a valid trimmed UUID", |v| {
+                v.len() == 32 && v.chars().all(|c| c.is_ascii_hexdigit())
+            }),
+        ];
+        for (name, message, is_valid) in formats {
+            if rule_is_true(rules, name) && !is_valid(value) {
+                violation(message.into());
+            }
+        }
+        check_const_and_lists(&Value::String(value.into()), rules, violation);
+    }
+
+    fn validate_bytes(
+        &self,
+        value: &[u8],
+        rules: &DynamicMessage,
+        violation: &mut impl FnMut(String),
+    ) {
+        check_bytes_size(value.len(), rules, "len", "min_len", "max_len", violation);
+        if let Some(pattern) =
+            rule(rules, "pattern").and_then(|p| p.as_str().map(ToOwned::to_owned))
+            && let Some(regex) = self.patterns.get(&pattern)
+        {
+            match std::str::from_utf8(value) {
+                Ok(value) if regex.is_match(value) => (),
+                Ok(_) => violation(format!("value does not match regex pattern `{pattern}`")),
+                Err(_) => violation("value must be valid UTF-8 to match a regex pattern".into()),
+            }
+        }
+        if let Some(prefix) = rule(rules, "prefix")
+            && let Some(prefix) = prefix.as_bytes()
+            && !value.starts_with(prefix)
+        {
+            violation("value does not have the expected prefix".into());
+        }
+        if let Some(suffix) = rule(rules, "suffix")
+            && let Some(suffix) = suffix.as_bytes()
+            && !value.ends_with(suffix)
+        {
+            violation("value does not have the expected suffix".into());
+        }
+        if let Some(contains) = rule(rules, "contains")
+            && let Some(contains) = contains.as_bytes()
+            && !contains.is_empty()
+            && !value
+                .windows(contains.len())
+                .any(|w| w == contains.as_ref())
+        {
+            violation("value does not contain the expected bytes".into());
+        }
+        // IP addresses are in network byte order
+        if rule_is_true(rules, "ip") && !matches!(value.len(), 4 | 16) {
+            violation("value must be a valid IP address".into());
+        }
+        if rule_is_true(rules, "ipv4") && value.len() != 4 {
+            violation("value must be a valid IPv4 address".into());
+        }
+        if rule_is_true(rules, "ipv6") && value.len() != 16 {
+            violation("value must be a valid IPv6 address".into());
+        }
+        check_const_and_lists(&Value::Bytes(value.to_vec().into()), rules, violation);
+    }
+}
+
+impl Violation {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+fn validate_any(any: &DynamicMessage, rules: &DynamicMessage, violation: &mut impl FnMut(String)) {
+    let type_url = any
+        .get_field_by_name("type_url")
+        .and_then(|v| v.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default();
+    if let Some(allowed) = rule(rules, "in")
+        && let Some(allowed) = allowed.as_list()
+        && !allowed.iter().any(|v| v.as_str() == Some(&type_url))
+    {
+        violation(format!(
+            "type URL must be in list {}",
+            Value::List(allowed.to_vec())
+        ));
+    }
+    if let Some(forbidden) = rule(rules, "not_in")
+        && let Some(forbidden) = forbidden.as_list()
+        && forbidden.iter().any(|v| v.as_str() == Some(&type_url))
+    {
+        violation(format!(
+            "type URL must not be in list {}",
+            Value::List(forbidden.to_vec())
+        ));
+    }
+}
+
+/// Checks the `lt_now`, `gt_now` and `within` timestamp rules
+fn validate_timestamp_now(
+    value: &Value,
+    rules: &DynamicMessage,
+    violation: &mut impl FnMut(String),
+) {
+    let Some(Number::Timestamp(timestamp)) = Number::from_value(value) else {
+        return;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as i128);
+    if rule_is_true(rules, "lt_now") && timestamp >= now {
+        violation("value must be less than now".into());
+    }
+    if rule_is_true(rules, "gt_now") && timestamp <= now {
+        violation("value must be greater than now".into());
+    }
+    if let Some(within) = rule(rules, "within").and_then(|v| Number::from_value(&v))
+        && let Number::Duration(nanos) = within
+        && (timestamp - now).abs() > nanos
+    {
+        violation(format!("value must be within {within} of now"));
+    }
+}
+
+fn validate_number(value: &Value, rules: &DynamicMessage, violation: &mut impl FnMut(String)) {
+    let Some(number) = Number::from_value(value) else {
+        return;
+    };
+    if rule_is_true(rules, "finite")
+        && let Number::Float(f) = number
+        && !f.is_finite()
+    {
+        violation("value must be finite".into());
+    }
+    let bound = |name| rule(rules, name).and_then(|v| Number::from_value(&v));
+    let lower = bound("gt")
+        .map(|b| Bound::Greater(b, false))
+        .or_else(|| bound("gte").map(|b| Bound::Greater(b, true)));
+    let upper = bound("lt")
+        .map(|b| Bound::Less(b, false))
+        .or_else(|| bound("lte").map(|b| Bound::Less(b, true)));
+    match (lower, upper) {
+        // Exclusive range: the value must be outside of [upper, lower]
+        (Some(lower), Some(upper)) if lower.value() > upper.value() => {
+            if !lower.accepts(number) && !upper.accepts(number) {
+                violation(format!("value must be {lower} or {upper}"));
+            }
+        }
+        (lower, upper) => {
+            for bound in [lower, upper].into_iter().flatten() {
+                if !bound.accepts(number) {
+                    violation(format!("value must be {bound}"));
+                }
+            }
+        }
+    }
+    check_const_and_lists(value, rules, violation);
+}
+
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && local.len() <= 64
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+/=?^_`{|}~.-".contains(c))
+        && is_hostname(domain)
+}
+
+fn is_hostname(value: &str) -> bool {
+    let value = value.strip_suffix('.').unwrap_or(value);
+    !value.is_empty()
+        && value.len() <= 253
+        && value.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        // The top level domain is not fully numeric
+        && !value
+            .rsplit('.')
+            .next()
+            .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn is_uuid(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(i, c)| {
+            if matches!(i, 8 | 13 | 18 | 23) {
+                c == '-'
+            } else {
+                c.is_ascii_hexdigit()
+            }
+        })
+}
+
+/// Checks the `const`, `in` and `not_in` rules shared by most types
+fn check_const_and_lists(
+    value: &Value,
+    rules: &DynamicMessage,
+    violation: &mut impl FnMut(String),
+) {
+    if let Some(expected) = rule(rules, "const")
+        && !values_equal(value, &expected)
+    {
+        violation(format!("value must equal {expected}"));
+    }
+    if let Some(allowed) = rule(rules, "in")
+        && let Some(allowed) = allowed.as_list()
+        && !allowed.iter().any(|v| values_equal(value, v))
+    {
+        violation(format!(
+            "value must be in list {}",
+            Value::List(allowed.to_vec())
+        ));
+    }
+    if let Some(forbidden) = rule(rules, "not_in")
+        && let Some(forbidden) = forbidden.as_list()
+        && forbidden.iter().any(|v| values_equal(value, v))
+    {
+        violation(format!(
+            "value must not be in list {}",
+            Value::List(forbidden.to_vec())
+        ));
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (Number::from_value(a), Number::from_value(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn check_size(
+    size: usize,
+    rules: &DynamicMessage,
+    min_rule: &str,
+    max_rule: &str,
+    unit: &str,
+    path: &str,
+    violations: &mut Vec<Violation>,
+) {
+    if let Some(min) = rule_u64(rules, min_rule)
+        && (size as u64) < min
+    {
+        violations.push(Violation::new(
+            path,
+            format!("value must contain at least {min} {unit}"),
+        ));
+    }
+    if let Some(max) = rule_u64(rules, max_rule)
+        && size as u64 > max
+    {
+        violations.push(Violation::new(
+            path,
+            format!("value must contain at most {max} {unit}"),
+        ));
+    }
+}
+
+fn check_bytes_size(
+    size: usize,
+    rules: &DynamicMessage,
+    len_rule: &str,
+    min_rule: &str,
+    max_rule: &str,
+    violation: &mut impl FnMut(String),
+) {
+    if let Some(len) = rule_u64(rules, len_rule)
+        && size as u64 != len
+    {
+        violation(format!("value length must be {len} bytes"));
+    }
+    if let Some(min) = rule_u64(rules, min_rule)
+        && (size as u64) < min
+    {
+        violation(format!("value length must be at least {min} bytes"));
+    }
+    if let Some(max) = rule_u64(rules, max_rule)
+        && size as u64 > max
+    {
+        violation(format!("value length must be at most {max} bytes"));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Number {
+    Int(i128),
+    Float(f64),
+    // google.protobuf.Duration and google.protobuf.Timestamp, in nanoseconds
+    Duration(i128),
+    Timestamp(i128),
+}
+
+impl Number {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::I32(v) => Self::Int((*v).into()),
+            Value::I64(v) => Self::Int((*v).into()),
+            Value::U32(v) => Self::Int((*v).into()),
+            Value::U64(v) => Self::Int((*v).into()),
+            Value::F32(v) => Self::Float((*v).into()),
+            Value::F64(v) => Self::Float(*v),
+            Value::EnumNumber(v) => Self::Int((*v).into()),
+            Value::Message(message) => {
+                let seconds = message.get_field_by_name("seconds")?.as_i64()?;
+                let nanos = message.get_field_by_name("nanos")?.as_i32()?;
+                let nanos = i128::from(seconds) * NANOS_PER_SECOND + i128::from(nanos);
+                match message.descriptor().full_name() {
+                    "google.protobuf.Duration" => Self::Duration(nanos),
+                    "google.protobuf.Timestamp" => Self::Timestamp(nanos),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(v) => v.fmt(f),
+            Self::Float(v) => v.fmt(f),
+            Self::Duration(v) => prost_types::Duration {
+                seconds: (v / NANOS_PER_SECOND) as i64,
+                nanos: (v % NANOS_PER_SECOND) as i32,
+            }
+            .fmt(f),
+            Self::Timestamp(v) => prost_types::Timestamp {
+                seconds: v.div_euclid(NANOS_PER_SECOND) as i64,
+                nanos: v.rem_euclid(NANOS_PER_SECOND) as i32,
+            }
+            .fmt(f),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Bound {
+    // (bound, inclusive)
+    Greater(Number, bool),
+    Less(Number, bool),
+}
+
+impl Bound {
+    fn value(self) -> Number {
+        match self {
+            Self::Greater(v, _) | Self::Less(v, _) => v,
+        }
+    }
+
+    fn accepts(self, number: Number) -> bool {
+        match self {
+            Self::Greater(bound, inclusive) => number > bound || (inclusive && number == bound),
+            Self::Less(bound, inclusive) => number < bound || (inclusive && number == bound),
+        }
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Greater(bound, false) => write!(f, "greater than {bound}"),
+            Self::Greater(bound, true) => write!(f, "greater than or equal to {bound}"),
+            Self::Less(bound, false) => write!(f, "less than {bound}"),
+            Self::Less(bound, true) => write!(f, "less than or equal to {bound}"),
+        }
+    }
+}
+
+/// Name of the `buf.validate.FieldRules` field holding the rules for a given type
+fn type_rules_name(kind: &Kind) -> Option<&'static str> {
+    Some(match kind {
+        Kind::Double => "double",
+        Kind::Float => "float",
+        Kind::Int32 => "int32",
+        Kind::Int64 => "int64",
+        Kind::Uint32 => "uint32",
+        Kind::Uint64 => "uint64",
+        Kind::Sint32 => "sint32",
+        Kind::Sint64 => "sint64",
+        Kind::Fixed32 => "fixed32",
+        Kind::Fixed64 => "fixed64",
+        Kind::Sfixed32 => "sfixed32",
+        Kind::Sfixed64 => "sfixed64",
+        Kind::Bool => "bool",
+        Kind::String => "string",
+        Kind::Bytes => "bytes",
+        Kind::Enum(_) => "enum",
+        Kind::Message(message) => match message.full_name() {
+            "google.protobuf.Any" => "any",
+            "google.protobuf.Duration" => "duration",
+            "google.protobuf.Timestamp" => "timestamp",
+            _ => return None,
+        },
+    })
+}
+
+/// Rules of each `buf.validate.FieldRules` type field that are checked
+fn supported_rules(type_rules_name: &str) -> Option<&'static [&'static str]> {
+    const NUMBER: &[&str] = &[
+        "const", "lt", "lte", "gt", "gte", "in", "not_in", "finite", "example",
+    ];
+    Some(match type_rules_name {
+        "float" | "double" | "int32" | "int64" | "uint32" | "uint64" | "sint32" | "sint64"
+        | "fixed32" | "fixed64" | "sfixed32" | "sfixed64" | "duration" => NUMBER,
+        "bool" => &["const", "example"],
+        "string" => &[
+            "const",
+            "len",
+            "min_len",
+            "max_len",
+            "len_bytes",
+            "min_bytes",
+            "max_bytes",
+            "pattern",
+            "prefix",
+            "suffix",
+            "contains",
+            "not_contains",
+            "in",
+            "not_in",
+            "email",
+            "hostname",
+            "ip",
+            "ipv4",
+            "ipv6",
+            "uuid",
+            "tuuid",
+            "example",
+        ],
+        "bytes" => &[
+            "const", "len", "min_len", "max_len", "pattern", "prefix", "suffix", "contains", "in",
+            "not_in", "ip", "ipv4", "ipv6", "example",
+        ],
+        "enum" => &["const", "defined_only", "in", "not_in", "example"],
+        "repeated" => &["min_items", "max_items", "unique", "items"],
+        "map" => &["min_pairs", "max_pairs", "keys", "values"],
+        "any" => &["in", "not_in"],
+        "timestamp" => &[
+            "const", "lt", "lte", "gt", "gte", "lt_now", "gt_now", "within", "example",
+        ],
+        _ => return None,
+    })
+}
+
+/// Rules set in a `buf.validate.FieldRules` that are not checked, e.g. `string.uri` or `cel`
+fn unsupported_rules(field_rules: &DynamicMessage) -> Vec<String> {
+    let mut unsupported = Vec::new();
+    for (field, value) in field_rules.fields() {
+        let name = field.name();
+        if matches!(name, "required" | "ignore") {
+            continue;
+        }
+        let (Some(supported), Value::Message(type_rules)) = (supported_rules(name), value) else {
+            unsupported.push(name.to_owned());
+            continue;
+        };
+        for (rule, value) in type_rules.fields() {
+            if !supported.contains(&rule.name()) {
+                unsupported.push(format!("{name}.{}", rule.name()));
+            } else if matches!(rule.name(), "items" | "keys" | "values")
+                && let Value::Message(nested) = value
+            {
+                unsupported.extend(
+                    unsupported_rules(nested)
+                        .into_iter()
+                        .map(|r| format!("{name}.{}.{r}", rule.name())),
+                );
+            }
+        }
+    }
+    unsupported
+}
+
+fn extension_message(
+    options: &DynamicMessage,
+    extension: &ExtensionDescriptor,
+) -> Option<DynamicMessage> {
+    if !options.has_extension(extension) {
+        return None;
+    }
+    options.get_extension(extension).as_message().cloned()
+}
+
+/// Returns the value of a rule if it is set
+fn rule<'a>(rules: &'a DynamicMessage, name: &str) -> Option<Cow<'a, Value>> {
+    let field = rules.descriptor().get_field_by_name(name)?;
+    rules.has_field(&field).then(|| rules.get_field(&field))
+}
+
+fn rule_message<'a>(rules: &'a DynamicMessage, name: &str) -> Option<&'a DynamicMessage> {
+    match rule(rules, name)? {
+        Cow::Borrowed(Value::Message(message)) => Some(message),
+        _ => None,
+    }
+}
+
+fn rule_u64(rules: &DynamicMessage, name: &str) -> Option<u64> {
+    rule(rules, name).and_then(|v| v.as_u64())
+}
+
+fn rule_is_true(rules: &DynamicMessage, name: &str) -> bool {
+    rule(rules, name).is_some_and(|v| v.as_bool() == Some(true))
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.into()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use prost_reflect::DescriptorPool;
+    use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+    use twurst_error::TwirpErrorCode;
+
+    /// Subset of `buf/validate/validate.proto` with the same field numbers
+    const VALIDATE_PROTO: &str = r#"
+        syntax = "proto2";
+        package buf.validate;
+        import "google/protobuf/descriptor.proto";
+        import "google/protobuf/duration.proto";
+        import "google/protobuf/timestamp.proto";
+
+        extend google.protobuf.FieldOptions { optional FieldRules field = 1159; }
+        extend google.protobuf.OneofOptions { optional OneofRules oneof = 1159; }
+
+        message OneofRules { optional bool required = 1; }
+
+        message FieldRules {
+          optional bool required = 25;
+          optional Ignore ignore = 27;
+          oneof type {
+            Int32Rules int32 = 3;
+            StringRules string = 14;
+            BytesRules bytes = 15;
+            EnumRules enum = 16;
+            RepeatedRules repeated = 18;
+            MapRules map = 19;
+            AnyRules any = 20;
+            DurationRules duration = 21;
+            TimestampRules timestamp = 22;
+          }
+        }
+
+        enum Ignore {
+          IGNORE_UNSPECIFIED = 0;
+          IGNORE_IF_ZERO_VALUE = 1;
+          IGNORE_ALWAYS = 3;
+        }
+
+        message Int32Rules {
+          optional int32 const = 1;
+          optional int32 lt = 2;
+          optional int32 lte = 3;
+          optional int32 gt = 4;
+          optional int32 gte = 5;
+          repeated int32 in = 6;
+          repeated int32 not_in = 7;
+        }
+
+        message StringRules {
+          optional string const = 1;
+          optional uint64 min_len = 2;
+          optional uint64 max_len = 3;
+          optional string pattern = 6;
+          optional string prefix = 7;
+          repeated string in = 10;
+          optional bool email = 12;
+          optional bool hostname = 13;
+          optional bool ip = 14;
+          optional bool ipv4 = 15;
+          optional bool ipv6 = 16;
+          optional bool uri = 17;
+          optional bool uuid = 22;
+        }
+
+        message BytesRules {
+          optional string pattern = 4;
+          optional bool ipv4 = 11;
+        }
+
+        message EnumRules {
+          optional int32 const = 1;
+          optional bool defined_only = 2;
+        }
+
+        message RepeatedRules {
+          optional uint64 min_items = 1;
+          optional uint64 max_items = 2;
+          optional bool unique = 3;
+          optional FieldRules items = 4;
+        }
+
+        message MapRules {
+          optional uint64 min_pairs = 1;
+          optional uint64 max_pairs = 2;
+          optional FieldRules keys = 4;
+          optional FieldRules values = 5;
+        }
+
+        message AnyRules {
+          repeated string in = 2;
+        }
+
+        message DurationRules {
+          optional google.protobuf.Duration lte = 4;
+          optional google.protobuf.Duration gt = 5;
+        }
+
+        message TimestampRules {
+          optional bool lt_now = 7;
+        }
+    "#;
+
+    const TEST_PROTO: &str = r##"
+        syntax = "proto3";
+        package test;
+        import "buf/validate/validate.proto";
+        import "google/protobuf/any.proto";
+        import "google/protobuf/duration.proto";
+        import "google/protobuf/timestamp.proto";
+        import "google/protobuf/wrappers.proto";
+
+        message CreateUser {
+          string name = 1 [(buf.validate.field).string = {min_len: 1, max_len: 10}];
+          string email = 2 [(buf.validate.field).string.pattern = "^[^@]+@[^@]+$"];
+          int32 age = 3 [(buf.validate.field).int32 = {gte: 0, lt: 150}];
+          repeated string tags = 4 [(buf.validate.field).repeated = {max_items: 2, unique: true, items: {string: {prefix: "#"}}}];
+          Address address = 5 [(buf.validate.field).required = true];
+          map<string, int32> scores = 6 [(buf.validate.field).map.values.int32.gt = 0];
+          Role role = 7 [(buf.validate.field).enum.defined_only = true];
+          oneof contact {
+            option (buf.validate.oneof).required = true;
+            string phone = 8;
+            string fax = 9;
+          }
+          optional string nickname = 10 [(buf.validate.field).string.min_len = 3];
+          string comment = 11 [(buf.validate.field).string.min_len = 3, (buf.validate.field).ignore = IGNORE_IF_ZERO_VALUE];
+          repeated Address other_addresses = 12;
+        }
+
+        message Address {
+          string city = 1 [(buf.validate.field).string.min_len = 1];
+        }
+
+        enum Role {
+          ROLE_UNSPECIFIED = 0;
+          ROLE_ADMIN = 1;
+        }
+
+        message NoRules {
+          string name = 1;
+        }
+
+        message Formats {
+          string email = 1 [(buf.validate.field).string.email = true];
+          string hostname = 2 [(buf.validate.field).string.hostname = true];
+          string ip = 3 [(buf.validate.field).string.ip = true];
+          string ipv4 = 4 [(buf.validate.field).string.ipv4 = true];
+          string ipv6 = 5 [(buf.validate.field).string.ipv6 = true];
+          string uuid = 6 [(buf.validate.field).string.uuid = true];
+          bytes code = 7 [(buf.validate.field).bytes.pattern = "^[A-Z]+$"];
+          bytes address = 8 [(buf.validate.field).bytes.ipv4 = true];
+          google.protobuf.Duration timeout = 9 [(buf.validate.field).duration = {gt: {}, lte: {seconds: 60}}];
+          google.protobuf.Timestamp created_at = 10 [(buf.validate.field).timestamp.lt_now = true];
+          google.protobuf.Any payload = 11 [(buf.validate.field).any.in = "type.googleapis.com/test.Address"];
+          google.protobuf.Int32Value count = 12 [(buf.validate.field).int32.gte = 0];
+          string url = 13 [(buf.validate.field).string.uri = true];
+        }
+    "##;
+
+    struct TestFileResolver;
+
+    impl FileResolver for TestFileResolver {
+        fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+            match name {
+                "buf/validate/validate.proto" => File::from_source(name, VALIDATE_PROTO),
+                "test.proto" => File::from_source(name, TEST_PROTO),
+                _ => Err(protox::Error::file_not_found(name)),
+            }
+        }
+    }
+
+    pub(crate) fn pool() -> DescriptorPool {
+        let mut resolver = ChainFileResolver::new();
+        resolver.add(GoogleFileResolver::new());
+        resolver.add(TestFileResolver);
+        let mut compiler = protox::Compiler::with_file_resolver(resolver);
+        compiler
+            .include_imports(true)
+            .open_file("test.proto")
+            .unwrap();
+        compiler.descriptor_pool()
+    }
+
+    fn validate(json: &str) -> Result<(), TwirpError> {
+        validate_message("test.CreateUser", json)
+    }
+
+    fn validate_message(name: &str, json: &str) -> Result<(), TwirpError> {
+        let descriptor = pool().get_message_by_name(name).unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let message = DynamicMessage::deserialize(descriptor.clone(), &mut deserializer).unwrap();
+        Validator::new(&descriptor).unwrap().validate(&message)
+    }
+
+    #[test]
+    fn valid_request() {
+        validate(
+            r##"{"name": "Alice", "email": "a@b", "age": 30, "tags": ["#a", "#b"], "address": {"city": "Berlin"}, "scores": {"a": 1}, "role": "ROLE_ADMIN", "phone": "123"}"##,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid_request() {
+        let error = validate(
+            r##"{"email": "nope", "age": 200, "tags": ["#a", "#a", "b"], "scores": {"a": 0}, "role": 5, "nickname": "ab", "other_addresses": [{"city": ""}]}"##,
+        )
+        .unwrap_err();
+        assert_eq!(error.code(), TwirpErrorCode::InvalidArgument);
+        let expected = [
+            ("name", "value length must be at least 1 characters"),
+            (
+                "email",
+                "value does not match regex pattern `^[^@]+@[^@]+$`",
+            ),
+            ("age", "value must be less than 150"),
+            (
+                "tags",
+                "value must contain at most 2 items, repeated value must contain unique items",
+            ),
+            ("tags[2]", "value does not have prefix `#`"),
+            ("address", "value is required"),
+            ("scores[\"a\"]", "value must be greater than 0"),
+            ("role", "value must be one of the defined enum values"),
+            ("contact", "exactly one field is required in oneof"),
+            ("nickname", "value length must be at least 3 characters"),
+            (
+                "other_addresses[0].city",
+                "value length must be at least 1 characters",
+            ),
+        ];
+        for (path, message) in expected {
+            assert_eq!(error.meta(path), Some(message), "{path} in {error:?}");
+        }
+        assert_eq!(error.meta("comment"), None);
+        assert_eq!(error.meta_iter().count(), expected.len(), "{error:?}");
+    }
+
+    #[test]
+    fn no_rules() {
+        let descriptor = pool().get_message_by_name("test.NoRules").unwrap();
+        assert!(Validator::new(&descriptor).is_none());
+    }
+
+    #[test]
+    fn valid_formats_and_well_known_types() {
+        validate_message(
+            "test.Formats",
+            r#"{"email": "a@example.com", "hostname": "example.com", "ip": "::1", "ipv4": "127.0.0.1", "ipv6": "::1", "uuid": "123e4567-e89b-12d3-a456-426614174000", "code": "QUJD", "address": "fwAAAQ==", "timeout": "30s", "createdAt": "2020-01-01T00:00:00Z", "payload": {"@type": "type.googleapis.com/test.Address", "city": "Berlin"}, "count": 1, "url": "not a URL"}"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid_formats_and_well_known_types() {
+        let error = validate_message(
+            "test.Formats",
+            r#"{"email": "a@-b", "hostname": "example.123", "ip": "1.2.3", "ipv4": "::1", "ipv6": "127.0.0.1", "uuid": "123e4567", "code": "YWJj", "address": "AQID", "timeout": "90s", "createdAt": "2999-01-01T00:00:00Z", "payload": {"@type": "type.googleapis.com/test.NoRules"}, "count": -1}"#,
+        )
+        .unwrap_err();
+        let expected = [
+            ("email", "value must be a valid email address"),
+            ("hostname", "value must be a valid hostname"),
+            ("ip", "value must be a valid IP address"),
+            ("ipv4", "value must be a valid IPv4 address"),
+            ("ipv6", "value must be a valid IPv6 address"),
+            ("uuid", "value must be a valid UUID"),
+            ("code", "value does not match regex pattern `^[A-Z]+$`"),
+            ("address", "value must be a valid IPv4 address"),
+            ("timeout", "value must be less than or equal to 60s"),
+            ("created_at", "value must be less than now"),
+            (
+                "payload",
+                "type URL must be in list [\"type.googleapis.com/test.Address\"]",
+            ),
+            ("count", "value must be greater than or equal to 0"),
+        ];
+        for (path, message) in expected {
+            assert_eq!(error.meta(path), Some(message), "{path} in {error:?}");
+        }
+        assert_eq!(error.meta_iter().count(), expected.len(), "{error:?}");
+    }
+
+    #[test]
+    fn unsupported_rules_are_listed() {
+        let pool = pool();
+        let descriptor = pool.get_message_by_name("test.Formats").unwrap();
+        let extension = pool.get_extension_by_name("buf.validate.field").unwrap();
+        let unsupported = descriptor
+            .fields()
+            .filter_map(|f| extension_message(&f.options(), &extension))
+            .flat_map(|r| unsupported_rules(&r))
+            .collect::<Vec<_>>();
+        assert_eq!(unsupported, ["string.uri"]);
+    }
+}